@@ -6,6 +6,15 @@ use serde::Deserialize;
 pub struct Config {
     pub http: Http,
     pub publisher: Publisher,
+    #[serde(default)]
+    pub record: Record,
+    #[serde(default)]
+    pub live: Live,
+    #[serde(default)]
+    pub database: Database,
+    // 配置文件中已不再使用的设置，启动时提示
+    #[serde(skip)]
+    pub deprecated: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -16,19 +25,165 @@ pub struct Http {
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Publisher {
-    pub max_retry_count: u32,
+    #[serde(default = "default_stall_timeout")]
+    pub stall_timeout: u64,
+    #[serde(default = "default_max_publishers")]
+    pub max_publishers: usize,
+    #[serde(default = "default_reconnect_delay")]
+    pub reconnect_delay: u64,
+    #[serde(default = "default_max_reconnect_delay")]
+    pub max_reconnect_delay: u64,
+    #[serde(default = "default_max_reconnect_count")]
+    pub max_reconnect_count: u32,
+    #[serde(default)]
+    pub reconnect_window: u64,
+    #[serde(default = "default_resume_concurrency")]
+    pub resume_concurrency: usize,
+    #[serde(default = "default_resume_timeout")]
+    pub resume_timeout: u64,
+    #[serde(default = "default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+}
+
+fn default_stall_timeout() -> u64 {
+    10
+}
+
+fn default_max_publishers() -> usize {
+    0
+}
+
+fn default_reconnect_delay() -> u64 {
+    1000
+}
+
+fn default_max_reconnect_delay() -> u64 {
+    60000
+}
+
+fn default_max_reconnect_count() -> u32 {
+    10
+}
+
+fn default_resume_concurrency() -> usize {
+    8
+}
+
+fn default_resume_timeout() -> u64 {
+    30
+}
+
+fn default_shutdown_timeout() -> u64 {
+    10
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Record {
+    pub path: String,
+    pub max_age: u64,
+    pub max_size: u64,
     pub interval_time: u64,
-    pub task_interval_time: u64,
+}
+
+impl Default for Record {
+    fn default() -> Self {
+        Record {
+            path: "record".to_string(),
+            max_age: 168,
+            max_size: 0,
+            interval_time: 600000,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Live {
+    pub path: String,
+    pub hls_time: u32,
+    pub hls_list_size: u32,
+}
+
+impl Default for Live {
+    fn default() -> Self {
+        Live {
+            path: "live".to_string(),
+            hls_time: 2,
+            hls_list_size: 6,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct Database {
+    pub path: String,
+    pub pool_size: u32,
+    pub busy_timeout: u64,
+}
+
+impl Default for Database {
+    fn default() -> Self {
+        Database {
+            path: "dudu.db".to_string(),
+            pool_size: 8,
+            busy_timeout: 5000,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Config {
     pub fn new() -> Self {
-        match util::fs::read_to_str("config.toml") {
+        let value: toml::Value = match util::fs::read_to_str("config.toml") {
             Ok(contents) => match toml::from_str(&contents) {
-                Ok(c) => c,
+                Ok(v) => v,
                 Err(e) => panic!("{}", e),
             },
             Err(e) => panic!("{}", e),
+        };
+        let mut config: Config = match value.clone().try_into() {
+            Ok(c) => c,
+            Err(e) => panic!("{}", e),
+        };
+        config.migrate(&value);
+        config
+    }
+
+    /// 旧版本通过定时任务重试异常的推流，现在由推流线程原地断线重连代替，
+    /// 没有设置新的重连参数时使用旧的重试设置
+    fn migrate(&mut self, value: &toml::Value) {
+        let publisher = match value.get("publisher") {
+            None => return,
+            Some(publisher) => publisher,
+        };
+        let get = |key: &str| publisher.get(key).and_then(|v| v.as_integer());
+        if let Some(count) = get("max_retry_count") {
+            if get("max_reconnect_count").is_none() {
+                self.publisher.max_reconnect_count = count as u32;
+            }
+            self.deprecated.push(
+                "publisher.max_retry_count is deprecated, use publisher.max_reconnect_count"
+                    .to_string(),
+            );
+        }
+        if let Some(interval) = get("interval_time") {
+            if get("reconnect_delay").is_none() {
+                self.publisher.reconnect_delay = interval as u64;
+            }
+            self.deprecated.push(
+                "publisher.interval_time is deprecated, use publisher.reconnect_delay".to_string(),
+            );
+        }
+        if get("task_interval_time").is_some() {
+            self.deprecated
+                .push("publisher.task_interval_time is deprecated and ignored".to_string());
         }
     }
 }
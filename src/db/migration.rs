@@ -0,0 +1,222 @@
+use crate::util;
+use rusqlite::{params, Connection, Result, Transaction};
+
+use log::info;
+
+/// 数据库结构的升级步骤，启动时按版本号顺序执行未执行过的步骤
+/// 已发布的步骤不能再修改，修改表结构时在最后添加新的步骤
+struct Migration {
+    version: u32,
+    description: &'static str,
+    up: fn(&Transaction) -> Result<()>,
+}
+
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "create tb_account and tb_ipc",
+        up: create_tables,
+    },
+    Migration {
+        version: 2,
+        description: "add push options to tb_ipc and create tb_ipc_output",
+        up: add_push_options,
+    },
+    Migration {
+        version: 3,
+        description: "replace tb_ipc.enable with state",
+        up: add_ipc_state,
+    },
+    Migration {
+        version: 4,
+        description: "add retry policy to tb_ipc",
+        up: add_retry_policy,
+    },
+    Migration {
+        version: 5,
+        description: "add tag to tb_ipc",
+        up: add_ipc_tag,
+    },
+    Migration {
+        version: 6,
+        description: "add version to tb_ipc",
+        up: add_ipc_version,
+    },
+];
+
+const CREATE_VERSION_TABLE_SQL: &str = "CREATE TABLE IF NOT EXISTS schema_version (version INTEGER NOT NULL,description VARCHAR(255) NOT NULL,apply_time BIGINT NOT NULL,PRIMARY KEY (version))";
+const GET_VERSION_SQL: &str = "SELECT IFNULL(MAX(version), 0) FROM schema_version";
+const INSERT_VERSION_SQL: &str =
+    "INSERT INTO schema_version(version, description, apply_time) VALUES(?,?,?)";
+
+/// 执行未执行过的升级步骤，每个步骤在一个事务中执行，返回升级后的版本号
+pub fn run(conn: &mut Connection) -> Result<u32> {
+    conn.execute(CREATE_VERSION_TABLE_SQL, params![])?;
+    let mut version: u32 = conn.query_row(GET_VERSION_SQL, params![], |row| row.get(0))?;
+    for migration in MIGRATIONS.iter() {
+        if migration.version <= version {
+            continue;
+        }
+        let tx = conn.transaction()?;
+        (migration.up)(&tx)?;
+        tx.execute(
+            INSERT_VERSION_SQL,
+            params![
+                migration.version,
+                migration.description,
+                util::time::current_timestamp() as i64
+            ],
+        )?;
+        tx.commit()?;
+        version = migration.version;
+        info!(
+            "Database migrated to version {}: {}",
+            version, migration.description
+        );
+    }
+    Ok(version)
+}
+
+/// 表中是否存在该字段
+fn has_column(tx: &Transaction, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = tx.prepare(&format!("PRAGMA table_info({})", table))?;
+    let rows = stmt.query_map(params![], |row| row.get::<_, String>(1))?;
+    for name in rows {
+        if name? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// 添加字段，字段已存在时跳过，兼容没有版本记录的旧数据库
+fn add_column(tx: &Transaction, table: &str, column: &str, definition: &str) -> Result<()> {
+    if !has_column(tx, table, column)? {
+        let sql = format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition);
+        tx.execute(&sql, params![])?;
+    }
+    Ok(())
+}
+
+fn create_tables(tx: &Transaction) -> Result<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS tb_account (uid INTEGER NOT NULL,username VARCHAR(15) NOT NULL UNIQUE,password VARCHAR(32) NOT NULL,token VARCHAR(32) NOT NULL UNIQUE,create_time BIGINT NOT NULL,update_time BIGINT NULL,PRIMARY KEY (uid));
+        CREATE TABLE IF NOT EXISTS tb_ipc (id INTEGER NOT NULL,key VARCHAR(32) NOT NULL UNIQUE,name VARCHAR(50) NOT NULL,rtsp VARCHAR(255) NOT NULL,rtmp VARCHAR(255) NOT NULL,enable TINYINT NOT NULL DEFAULT 0,reason VARCHAR(255) NULL,retry_count INTEGER NOT NULL DEFAULT 0,create_time BIGINT NOT NULL,update_time BIGINT NULL,PRIMARY KEY (id));",
+    )
+}
+
+fn add_push_options(tx: &Transaction) -> Result<()> {
+    let columns = [
+        ("output_format", "VARCHAR(16) NOT NULL DEFAULT 'flv'"),
+        ("transport", "VARCHAR(16) NOT NULL DEFAULT 'tcp'"),
+        ("input_options", "TEXT NULL"),
+        ("output_options", "TEXT NULL"),
+        ("video_codec", "VARCHAR(16) NOT NULL DEFAULT 'copy'"),
+        ("video_bitrate", "INTEGER NOT NULL DEFAULT 0"),
+        ("video_gop", "INTEGER NOT NULL DEFAULT 0"),
+        ("video_preset", "VARCHAR(16) NOT NULL DEFAULT 'veryfast'"),
+        ("video_width", "INTEGER NOT NULL DEFAULT 0"),
+        ("video_height", "INTEGER NOT NULL DEFAULT 0"),
+        ("audio_codec", "VARCHAR(16) NOT NULL DEFAULT 'copy'"),
+        ("record", "TINYINT NOT NULL DEFAULT 0"),
+        ("record_format", "VARCHAR(16) NOT NULL DEFAULT 'ts'"),
+        ("record_segment", "INTEGER NOT NULL DEFAULT 60"),
+        ("live", "TINYINT NOT NULL DEFAULT 0"),
+    ];
+    for (column, definition) in columns.iter() {
+        add_column(tx, "tb_ipc", column, definition)?;
+    }
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS tb_ipc_output (id INTEGER NOT NULL,ipc_id INTEGER NOT NULL,url VARCHAR(255) NOT NULL,output_format VARCHAR(16) NOT NULL DEFAULT 'flv',output_options TEXT NULL,enable TINYINT NOT NULL DEFAULT 1,status TINYINT NOT NULL DEFAULT 0,reason VARCHAR(255) NULL,create_time BIGINT NOT NULL,update_time BIGINT NULL,PRIMARY KEY (id));",
+    )
+}
+
+fn add_ipc_state(tx: &Transaction) -> Result<()> {
+    add_column(
+        tx,
+        "tb_ipc",
+        "state",
+        "VARCHAR(16) NOT NULL DEFAULT 'disabled'",
+    )?;
+    add_column(tx, "tb_ipc", "state_time", "BIGINT NULL")?;
+    // 旧版本中已启用的Ipc在启动时恢复推流，推流出错的Ipc标记为失败并保留错误原因，
+    // enable 字段保留但不再使用
+    if has_column(tx, "tb_ipc", "enable")? {
+        tx.execute_batch(
+            "UPDATE tb_ipc SET state='starting' WHERE enable=1;
+            UPDATE tb_ipc SET state='failed' WHERE enable=0 AND IFNULL(reason, '')<>'';",
+        )?;
+    }
+    Ok(())
+}
+
+fn add_retry_policy(tx: &Transaction) -> Result<()> {
+    add_column(tx, "tb_ipc", "retry_max_attempts", "INTEGER NULL")?;
+    add_column(tx, "tb_ipc", "retry_delay", "BIGINT NULL")?;
+    add_column(tx, "tb_ipc", "retry_max_delay", "BIGINT NULL")?;
+    add_column(tx, "tb_ipc", "retry_window", "BIGINT NULL")
+}
+
+fn add_ipc_tag(tx: &Transaction) -> Result<()> {
+    add_column(tx, "tb_ipc", "tag", "VARCHAR(50) NULL")?;
+    tx.execute_batch("CREATE INDEX IF NOT EXISTS idx_ipc_tag ON tb_ipc (tag);")
+}
+
+fn add_ipc_version(tx: &Transaction) -> Result<()> {
+    add_column(tx, "tb_ipc", "version", "INTEGER NOT NULL DEFAULT 0")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrades_baseline_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        // 没有版本记录的旧数据库
+        conn.execute_batch(
+            "CREATE TABLE tb_ipc (id INTEGER NOT NULL,key VARCHAR(32) NOT NULL UNIQUE,name VARCHAR(50) NOT NULL,rtsp VARCHAR(255) NOT NULL,rtmp VARCHAR(255) NOT NULL,enable TINYINT NOT NULL DEFAULT 0,reason VARCHAR(255) NULL,retry_count INTEGER NOT NULL DEFAULT 0,create_time BIGINT NOT NULL,update_time BIGINT NULL,PRIMARY KEY (id));
+            INSERT INTO tb_ipc(id, key, name, rtsp, rtmp, enable, reason, create_time) VALUES(1, 'k1', 'n1', 'rtsp://a', 'rtmp://a', 1, NULL, 0);
+            INSERT INTO tb_ipc(id, key, name, rtsp, rtmp, enable, reason, create_time) VALUES(2, 'k2', 'n2', 'rtsp://b', 'rtmp://b', 0, 'Connection refused', 0);
+            INSERT INTO tb_ipc(id, key, name, rtsp, rtmp, enable, reason, create_time) VALUES(3, 'k3', 'n3', 'rtsp://c', 'rtmp://c', 0, NULL, 0);",
+        )
+        .unwrap();
+
+        let latest = MIGRATIONS.last().unwrap().version;
+        assert_eq!(run(&mut conn).unwrap(), latest);
+
+        let state = |id: i32| -> String {
+            conn.query_row("SELECT state FROM tb_ipc WHERE id=?", params![id], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        assert_eq!(state(1), "starting");
+        assert_eq!(state(2), "failed");
+        assert_eq!(state(3), "disabled");
+
+        let (output_format, transport, version, tag): (String, String, i32, Option<String>) = conn
+            .query_row(
+                "SELECT output_format, transport, version, tag FROM tb_ipc WHERE id=1",
+                params![],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(output_format, "flv");
+        assert_eq!(transport, "tcp");
+        assert_eq!(version, 0);
+        assert_eq!(tag, None);
+
+        // 再次执行时不做任何修改
+        let count = |conn: &Connection| -> u32 {
+            conn.query_row("SELECT COUNT(1) FROM schema_version", params![], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        let applied = count(&conn);
+        assert_eq!(applied, MIGRATIONS.len() as u32);
+        assert_eq!(run(&mut conn).unwrap(), latest);
+        assert_eq!(count(&conn), applied);
+    }
+}
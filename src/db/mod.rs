@@ -0,0 +1,39 @@
+mod migration;
+
+use crate::config;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{ffi, Error, Result};
+use std::time::Duration;
+
+pub type Connection = r2d2::PooledConnection<SqliteConnectionManager>;
+
+/// 数据库连接池，每个连接打开时开启 WAL 模式并设置锁等待时间
+#[derive(Clone)]
+pub struct Pool(r2d2::Pool<SqliteConnectionManager>);
+
+impl Pool {
+    pub fn new(config: &config::Database) -> Result<Self, r2d2::Error> {
+        let busy_timeout = Duration::from_millis(config.busy_timeout);
+        let manager = SqliteConnectionManager::file(&config.path).with_init(move |conn| {
+            conn.execute_batch("PRAGMA journal_mode=WAL;")?;
+            conn.busy_timeout(busy_timeout)
+        });
+        let pool = r2d2::Pool::builder()
+            .max_size(config.pool_size)
+            .build(manager)?;
+        Ok(Pool(pool))
+    }
+
+    /// 从连接池中获取连接，等待超时时返回数据库繁忙
+    pub fn conn(&self) -> Result<Connection> {
+        self.0.get().map_err(|e| {
+            Error::SqliteFailure(ffi::Error::new(ffi::SQLITE_BUSY), Some(e.to_string()))
+        })
+    }
+
+    /// 升级数据库结构，返回升级后的版本号
+    pub fn migrate(&self) -> Result<u32> {
+        let mut conn = self.conn()?;
+        migration::run(&mut conn)
+    }
+}
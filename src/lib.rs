@@ -10,6 +10,9 @@ pub mod my_actor;
 // 推流器核心部分
 pub mod publisher;
 
+// 定时任务
+pub mod scheduler;
+
 // 通用的 HTTP 接口返回
 pub mod result;
 
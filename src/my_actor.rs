@@ -1,24 +1,340 @@
 use super::publisher;
+use super::publisher::{OutputTarget, Stats, LIVE_TARGET_ID, RECORD_TARGET_ID};
 use super::service;
+use super::service::ipc::IpcState;
 use super::util;
+use actix::clock::{self, Duration};
 use actix::prelude::*;
-use async_std::task;
-use log::{error, info};
+use futures::channel::oneshot;
+use futures::future::{self, Either};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::sync::Arc;
+use std::thread;
+
+/// 推流命令执行失败的原因
+#[derive(Debug, Clone)]
+pub enum CommandError {
+    NotFound,
+    AlreadyRunning,
+    NotRunning,
+    Db(String),
+    Failed(String),       // 推流启动失败
+    Reconnecting(String), // 第一次推流没有成功，推流线程正在重连
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::NotFound => write!(f, "Ipc not found"),
+            CommandError::AlreadyRunning => write!(f, "Already running"),
+            CommandError::NotRunning => write!(f, "Not running"),
+            CommandError::Db(e) => write!(f, "{}", e),
+            CommandError::Failed(e) => write!(f, "{}", e),
+            CommandError::Reconnecting(e) => write!(f, "Reconnecting: {}", e),
+        }
+    }
+}
+
+/// 在独立线程中运行的推流
+pub struct PublisherThread {
+    pub publisher: Arc<publisher::Publisher>,
+    handle: thread::JoinHandle<()>,
+    start_time: i64,
+    input: String,
+    outputs: Vec<String>,
+    // 推流线程通知的最新状态
+    state: IpcState,
+    reason: Option<String>,
+    retry_count: i32,
+    // 等待开始推流的启动命令
+    started: Option<oneshot::Sender<Result<(), CommandError>>>,
+    // 等待线程结束的停止命令
+    finished: Vec<oneshot::Sender<()>>,
+}
+
+impl PublisherThread {
+    fn info(&self, stopping: bool) -> RuntimePublisher {
+        RuntimePublisher {
+            id: self.publisher.id,
+            thread: self.handle.thread().name().unwrap_or_default().to_string(),
+            start_time: self.start_time,
+            input: self.input.clone(),
+            outputs: self.outputs.clone(),
+            state: self.state,
+            stopping,
+        }
+    }
+
+    /// 等待推流线程结束
+    pub fn join(self) {
+        let id = self.publisher.id;
+        for finished in self.finished {
+            let _ = finished.send(());
+        }
+        if self.handle.join().is_err() {
+            error!("id: {} publisher thread panicked", id);
+        }
+    }
+}
 
 pub struct MyActor {
-    pub publisher_list: Vec<Arc<publisher::Publisher>>,
+    pub publisher_list: Vec<PublisherThread>,
+    // 已请求停止但线程还没有结束的推流
+    pub stopping_list: Vec<PublisherThread>,
+    // 超过最大推流数量时等待启动的推流
+    pending: VecDeque<(i32, oneshot::Sender<Result<(), CommandError>>)>,
     pub service: Arc<service::Service>,
+    // 程序正在退出，不再启动新的推流
+    shutting_down: bool,
 }
 impl MyActor {
-    pub fn new() -> Self {
+    pub fn new(service: Arc<service::Service>) -> Self {
         MyActor {
             publisher_list: Vec::new(),
-            service: Arc::new(service::Service::new()),
+            stopping_list: Vec::new(),
+            pending: VecDeque::new(),
+            service,
+            shutting_down: false,
         }
     }
     pub fn get_index(&self, id: i32) -> Option<usize> {
-        self.publisher_list.iter().position(|x| x.id == id)
+        self.publisher_list
+            .iter()
+            .position(|x| x.publisher.id == id)
+    }
+
+    fn get_ipc(&self, id: i32) -> Result<service::ipc::Ipc, CommandError> {
+        match self.service.ipc_service.get(id) {
+            Err(e) => Err(CommandError::Db(e.to_string())),
+            Ok(None) => Err(CommandError::NotFound),
+            Ok(Some(ipc)) => Ok(ipc),
+        }
+    }
+
+    /// 修改推流状态，所有状态变化都在这里写入数据库
+    fn set_state(&self, id: i32, state: IpcState, reason: Option<String>) {
+        info!("id: {} state {} {:?}", id, state.as_str(), reason);
+        if let Err(e) = self.service.ipc_service.update_state(id, state, reason) {
+            error!("{}", e);
+        }
+    }
+
+    fn is_pending(&self, id: i32) -> bool {
+        self.pending.iter().any(|(pending_id, _)| *pending_id == id)
+    }
+
+    /// 推流线程的数量是否已达到上限，正在停止的推流同样占用线程
+    fn is_full(&self) -> bool {
+        let max_publishers = self.service.publisher_config.max_publishers;
+        max_publishers > 0 && self.publisher_list.len() + self.stopping_list.len() >= max_publishers
+    }
+
+    /// 启动推流，返回开始推流或者启动失败时的通知
+    /// 推流数量达到上限时等待其它推流结束后再启动
+    fn start(
+        &mut self,
+        id: i32,
+        ctx: &mut Context<Self>,
+    ) -> Result<oneshot::Receiver<Result<(), CommandError>>, CommandError> {
+        if self.shutting_down {
+            return Err(CommandError::Failed("Shutting down".to_string()));
+        }
+        let ipc = self.get_ipc(id)?;
+        // 同一个Ipc只能有一个推流，正在停止的推流结束前也不能重新启动
+        if self.get_index(id).is_some()
+            || self.stopping_list.iter().any(|cmd| cmd.publisher.id == id)
+            || self.is_pending(id)
+        {
+            info!("id: {} is already running", id);
+            return Err(CommandError::AlreadyRunning);
+        }
+        let (started, started_rx) = oneshot::channel();
+        if self.is_full() {
+            info!(
+                "id: {} waiting for a free publisher, max {}",
+                id, self.service.publisher_config.max_publishers
+            );
+            self.pending.push_back((id, started));
+            self.set_state(id, IpcState::Starting, None);
+        } else {
+            self.spawn(ipc, started, ctx)?;
+        }
+        Ok(started_rx)
+    }
+
+    /// 有空闲的推流线程时启动等待中的推流
+    fn start_pending(&mut self, ctx: &mut Context<Self>) {
+        while !self.shutting_down && !self.is_full() {
+            let (id, started) = match self.pending.pop_front() {
+                None => break,
+                Some(pending) => pending,
+            };
+            let result = match self.get_ipc(id) {
+                Err(e) => {
+                    let _ = started.send(Err(e.clone()));
+                    Err(e)
+                }
+                Ok(ipc) => self.spawn(ipc, started, ctx),
+            };
+            if let Err(e) = result {
+                error!("id: {} start pending: {}", id, e);
+            }
+        }
+    }
+
+    /// 启动推流线程，开始推流或者启动失败时通知 `started`
+    fn spawn(
+        &mut self,
+        ipc: service::ipc::Ipc,
+        started: oneshot::Sender<Result<(), CommandError>>,
+        ctx: &mut Context<Self>,
+    ) -> Result<(), CommandError> {
+        let id = ipc.id;
+        let outputs = match self.service.output_service.get_enable_list(id) {
+            Err(e) => {
+                let e = CommandError::Db(e.to_string());
+                let _ = started.send(Err(e.clone()));
+                return Err(e);
+            }
+            Ok(outputs) => outputs,
+        };
+        // 本地录像和直播
+        let mut local_targets = Vec::new();
+        if ipc.record == 1 {
+            let dir = self.service.record_service.dir(id);
+            match OutputTarget::record(&ipc, &dir) {
+                Ok(target) => local_targets.push(target),
+                Err(e) => error!("id: {} record: {}", id, e),
+            }
+        }
+        if ipc.live == 1 {
+            let live_service = &self.service.live_service;
+            let dir = live_service.dir(id);
+            match OutputTarget::live(&dir, live_service.hls_time, live_service.hls_list_size) {
+                Ok(target) => local_targets.push(target),
+                Err(e) => error!("id: {} live: {}", id, e),
+            }
+        }
+        let input = ipc.rtsp.clone();
+        let mut output_urls = vec![ipc.rtmp.clone()];
+        output_urls.extend(outputs.iter().map(|output| output.url.clone()));
+        output_urls.extend(local_targets.iter().map(|target| target.url.clone()));
+        let cmd = publisher::Publisher::new(id, self.service.publisher_config.stall_timeout);
+        // Ipc没有设置重连参数时使用配置文件中的设置
+        let publisher_config = &self.service.publisher_config;
+        let backoff = publisher::Backoff::new(
+            ipc.retry_delay
+                .map_or(publisher_config.reconnect_delay, |v| v as u64),
+            ipc.retry_max_delay
+                .map_or(publisher_config.max_reconnect_delay, |v| v as u64),
+            ipc.retry_max_attempts
+                .map_or(publisher_config.max_reconnect_count, |v| v as u32),
+            ipc.retry_window
+                .map_or(publisher_config.reconnect_window, |v| v as u64),
+        );
+        let cmd_arc = Arc::new(cmd);
+        let cmd_arc_clone = cmd_arc.clone();
+        let addr = ctx.address();
+        // 推流全部是阻塞调用，每路推流使用一个线程，避免占用异步任务的线程
+        let spawn_result = thread::Builder::new()
+            .name(format!("publisher-{}", id))
+            .spawn(move || {
+                // 推流地址状态的变化交给Actor写入数据库
+                let status_addr = addr.clone();
+                let status_cmd = Arc::clone(&cmd_arc_clone);
+                let on_status = move |output_id: i32, status: i32, reason: Option<String>| {
+                    if output_id == RECORD_TARGET_ID || output_id == LIVE_TARGET_ID {
+                        info!(
+                            "id: {} local output {} status {} {:?}",
+                            id, output_id, status, reason
+                        );
+                        return;
+                    }
+                    status_addr.do_send(SetOutputStatus(
+                        Arc::clone(&status_cmd),
+                        output_id,
+                        status,
+                        reason,
+                    ));
+                };
+                // 推流状态的变化交给Actor写入数据库
+                let state_addr = addr.clone();
+                let state_cmd = Arc::clone(&cmd_arc_clone);
+                let on_state = move |state: IpcState, reason: Option<String>| {
+                    state_addr.do_send(SetState(Arc::clone(&state_cmd), state, reason));
+                };
+                let start_result = cmd_arc_clone.run(
+                    &ipc,
+                    &outputs,
+                    local_targets,
+                    &on_status,
+                    backoff,
+                    &on_state,
+                );
+                addr.do_send(Finished(cmd_arc_clone, start_result));
+            });
+        match spawn_result {
+            Ok(handle) => {
+                self.publisher_list.push(PublisherThread {
+                    publisher: cmd_arc,
+                    handle,
+                    start_time: util::time::current_timestamp() as i64,
+                    input,
+                    outputs: output_urls,
+                    state: IpcState::Starting,
+                    reason: None,
+                    retry_count: 0,
+                    started: Some(started),
+                    finished: Vec::new(),
+                });
+                self.set_state(id, IpcState::Starting, None);
+                Ok(())
+            }
+            Err(e) => {
+                let reason = format!("Failed to spawn publisher thread: {}", e);
+                error!("id: {} {}", id, reason);
+                self.set_state(id, IpcState::Failed, Some(reason.clone()));
+                let e = CommandError::Failed(reason);
+                let _ = started.send(Err(e.clone()));
+                Err(e)
+            }
+        }
+    }
+
+    /// 停止推流，推流线程还在运行时返回线程结束的通知
+    fn stop(&mut self, id: i32) -> Result<Option<oneshot::Receiver<()>>, CommandError> {
+        let ipc = self.get_ipc(id)?;
+        let mut finished_rx = None;
+        match self.get_index(id) {
+            Some(index) if self.publisher_list[index].publisher.stop() => {
+                let mut cmd = self.publisher_list.remove(index);
+                let (finished, rx) = oneshot::channel();
+                cmd.finished.push(finished);
+                finished_rx = Some(rx);
+                self.stopping_list.push(cmd);
+            }
+            _ => {
+                // 等待启动的推流直接移除，启动命令会收到停止的通知
+                let pending = self
+                    .pending
+                    .iter()
+                    .position(|(pending_id, _)| *pending_id == id);
+                match pending {
+                    Some(index) => {
+                        self.pending.remove(index);
+                    }
+                    None if ipc.state == IpcState::Disabled => {
+                        return Err(CommandError::NotRunning);
+                    }
+                    None => {}
+                }
+            }
+        }
+        self.set_state(id, IpcState::Disabled, None);
+        Ok(finished_rx)
     }
 }
 
@@ -26,75 +342,434 @@ impl Actor for MyActor {
     type Context = Context<Self>;
 }
 
+/// 启动推流，开始推流或者启动失败后返回
 #[derive(Message)]
-#[rtype(result = "i32")]
-pub struct Ping(pub i32, pub i32);
+#[rtype(result = "Result<(), CommandError>")]
+pub struct Start(pub i32);
 
-impl Handler<Ping> for MyActor {
-    type Result = i32;
+impl Handler<Start> for MyActor {
+    type Result = ResponseFuture<Result<(), CommandError>>;
 
-    fn handle(&mut self, msg: Ping, _ctx: &mut Context<Self>) -> Self::Result {
+    fn handle(&mut self, msg: Start, ctx: &mut Context<Self>) -> Self::Result {
+        let started = self.start(msg.0, ctx);
+        Box::pin(async move { wait_started(started?).await })
+    }
+}
+
+async fn wait_started(
+    started: oneshot::Receiver<Result<(), CommandError>>,
+) -> Result<(), CommandError> {
+    match started.await {
+        Ok(result) => result,
+        Err(_) => Err(CommandError::Failed("Stopped before streaming".to_string())),
+    }
+}
+
+/// 停止推流，推流线程写完封装尾并结束后返回
+#[derive(Message)]
+#[rtype(result = "Result<(), CommandError>")]
+pub struct Stop(pub i32);
+
+impl Handler<Stop> for MyActor {
+    type Result = ResponseFuture<Result<(), CommandError>>;
+
+    fn handle(&mut self, msg: Stop, _ctx: &mut Context<Self>) -> Self::Result {
+        let finished = self.stop(msg.0);
+        Box::pin(async move {
+            if let Some(finished) = finished? {
+                let _ = finished.await;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// 重启推流，等待原来的推流线程结束后重新启动，没有推流时直接启动
+#[derive(Message)]
+#[rtype(result = "Result<(), CommandError>")]
+pub struct Restart(pub i32);
+
+impl Handler<Restart> for MyActor {
+    type Result = ResponseFuture<Result<(), CommandError>>;
+
+    fn handle(&mut self, msg: Restart, ctx: &mut Context<Self>) -> Self::Result {
         let id = msg.0;
-        let ipc_result = self.service.ipc_service.get(id);
-        if ipc_result.is_err() {
-            error!("{}", ipc_result.unwrap_err());
-            return -1;
-        }
-        let option_ipc = ipc_result.unwrap();
-        if option_ipc.is_none() {
-            error!("id: {} not found", id);
-            return -2;
-        }
-        let ipc = option_ipc.unwrap();
-        if msg.1 == 0 {
-            if self.publisher_list.len() > 0 {
-                match self.get_index(id) {
-                    None => {}
-                    Some(index) => {
-                        let cmd = &self.publisher_list[index];
-                        if cmd.stop() == true {
-                            self.publisher_list.remove(index);
-                        }
-                    }
-                };
+        let finished = self.stop(id);
+        let addr = ctx.address();
+        Box::pin(async move {
+            match finished {
+                Ok(Some(finished)) => {
+                    let _ = finished.await;
+                }
+                Ok(None) | Err(CommandError::NotRunning) => {}
+                Err(e) => return Err(e),
+            }
+            match addr.send(Start(id)).await {
+                Ok(result) => result,
+                Err(e) => Err(CommandError::Failed(e.to_string())),
+            }
+        })
+    }
+}
+
+/// Ipc的推流状态
+#[derive(Debug, Serialize)]
+pub struct IpcStatus {
+    pub id: i32,
+    pub state: IpcState,
+    pub state_time: Option<i64>,
+    pub reason: Option<String>,
+    pub running: bool, // 推流线程是否在当前进程中运行
+    pub stats: Option<Stats>,
+}
+
+/// 查询推流状态
+#[derive(Message)]
+#[rtype(result = "Result<IpcStatus, CommandError>")]
+pub struct QueryStatus(pub i32);
+
+impl Handler<QueryStatus> for MyActor {
+    type Result = Result<IpcStatus, CommandError>;
+
+    fn handle(&mut self, msg: QueryStatus, _ctx: &mut Context<Self>) -> Self::Result {
+        let ipc = self.get_ipc(msg.0)?;
+        let running = self
+            .get_index(ipc.id)
+            .map(|index| &self.publisher_list[index].publisher);
+        Ok(IpcStatus {
+            id: ipc.id,
+            state: ipc.state,
+            state_time: ipc.state_time,
+            reason: ipc.reason,
+            running: running.is_some(),
+            stats: running.map(|publisher| publisher.stats()),
+        })
+    }
+}
+
+/// 当前进程中运行的推流
+#[derive(Debug, Serialize)]
+pub struct RuntimePublisher {
+    pub id: i32,
+    pub thread: String,
+    pub start_time: i64,
+    pub input: String,
+    pub outputs: Vec<String>, // Ipc的推流地址、附加推流地址和本地的录像、直播地址
+    pub state: IpcState,
+    pub stopping: bool, // 已请求停止，线程还没有结束
+}
+
+/// 数据库中的推流状态与当前进程不一致的Ipc
+#[derive(Debug, Serialize)]
+pub struct Drift {
+    pub id: i32,
+    pub db_state: Option<IpcState>,      // Ipc已删除时为空
+    pub runtime_state: Option<IpcState>, // 没有在当前进程中运行时为空
+}
+
+#[derive(Debug, Serialize)]
+pub struct Runtime {
+    pub publishers: Vec<RuntimePublisher>,
+    pub drift: Vec<Drift>,
+}
+
+impl MyActor {
+    /// 对比数据库和当前进程中的推流状态
+    fn drift(&self) -> Result<Vec<Drift>, CommandError> {
+        let mut drift = Vec::new();
+        let ipc_service = &self.service.ipc_service;
+        let active_list = ipc_service
+            .get_active_list()
+            .map_err(|e| CommandError::Db(e.to_string()))?;
+        let mut db_states: HashMap<i32, IpcState> = HashMap::new();
+        for ipc in active_list {
+            if self.get_index(ipc.id).is_none() && !self.is_pending(ipc.id) {
+                drift.push(Drift {
+                    id: ipc.id,
+                    db_state: Some(ipc.state),
+                    runtime_state: None,
+                });
             }
-        } else if msg.1 == 1 {
-            let rtsp = ipc.rtsp;
-            let rtmp = ipc.rtmp;
-            let cmd = publisher::Publisher::new(id);
-            let cmd_arc = Arc::new(cmd);
-            self.publisher_list.push(cmd_arc.clone());
-            let cmd_arc_clone = cmd_arc.clone();
-            let service_arc = Arc::clone(&self.service);
-            task::spawn(async move {
-                let start_result = cmd_arc_clone.start(&rtsp, &rtmp).await;
-                match service_arc.ipc_service.get(id) {
-                    Err(e) => {
-                        error!("{}", &e.to_string());
+            db_states.insert(ipc.id, ipc.state);
+        }
+        // 数据库中不是推流状态的Ipc一次查询出来
+        let inactive: Vec<i32> = self
+            .publisher_list
+            .iter()
+            .map(|cmd| cmd.publisher.id)
+            .filter(|id| !db_states.contains_key(id))
+            .collect();
+        if !inactive.is_empty() {
+            let states = ipc_service
+                .get_states(&inactive)
+                .map_err(|e| CommandError::Db(e.to_string()))?;
+            db_states.extend(states);
+        }
+        for cmd in self.publisher_list.iter() {
+            let id = cmd.publisher.id;
+            let db_state = db_states.get(&id).copied();
+            if db_state != Some(cmd.state) {
+                drift.push(Drift {
+                    id,
+                    db_state,
+                    runtime_state: Some(cmd.state),
+                });
+            }
+        }
+        Ok(drift)
+    }
+}
+
+/// 获取当前进程中运行的推流，以及与数据库不一致的Ipc
+#[derive(Message)]
+#[rtype(result = "Result<Runtime, CommandError>")]
+pub struct GetRuntime;
+
+impl Handler<GetRuntime> for MyActor {
+    type Result = Result<Runtime, CommandError>;
+
+    fn handle(&mut self, _msg: GetRuntime, _ctx: &mut Context<Self>) -> Self::Result {
+        let running = self.publisher_list.iter().map(|cmd| cmd.info(false));
+        let stopping = self.stopping_list.iter().map(|cmd| cmd.info(true));
+        Ok(Runtime {
+            publishers: running.chain(stopping).collect(),
+            drift: self.drift()?,
+        })
+    }
+}
+
+/// 修复数据库和当前进程中不一致的推流状态，返回修复前的差异
+/// 数据库中为推流状态但没有运行时启动推流，Ipc已删除时停止推流，其它情况以当前进程的状态为准
+#[derive(Message)]
+#[rtype(result = "Result<Vec<Drift>, CommandError>")]
+pub struct Reconcile;
+
+impl Handler<Reconcile> for MyActor {
+    type Result = Result<Vec<Drift>, CommandError>;
+
+    fn handle(&mut self, _msg: Reconcile, ctx: &mut Context<Self>) -> Self::Result {
+        let drift = self.drift()?;
+        for item in drift.iter() {
+            match (item.db_state, item.runtime_state) {
+                (_, None) => {
+                    if let Err(e) = self.start(item.id, ctx) {
+                        error!("id: {} reconcile start: {}", item.id, e);
                     }
-                    Ok(option_ipc) => match option_ipc.clone() {
-                        None => {}
-                        Some(mut db_ipc) => {
-                            db_ipc.enable = 0;
-                            let update_time = util::time::current_timestamp();
-                            db_ipc.update_time = Some(update_time as i64);
-                            if start_result.is_ok() {
-                                db_ipc.reason = None;
-                                db_ipc.retry_count = 0;
-                            } else {
-                                db_ipc.reason = Some(start_result.unwrap_err());
-                            }
-                            match service_arc.ipc_service.update(db_ipc) {
-                                Ok(_) => {}
-                                Err(e) => error!("{}", &e.to_string()),
-                            }
+                }
+                (None, Some(_)) => {
+                    if let Some(index) = self.get_index(item.id) {
+                        if self.publisher_list[index].publisher.stop() {
+                            let cmd = self.publisher_list.remove(index);
+                            self.stopping_list.push(cmd);
                         }
-                    },
+                    }
                 }
-            });
+                (Some(_), Some(state)) => {
+                    let reason = self
+                        .get_index(item.id)
+                        .and_then(|index| self.publisher_list[index].reason.clone());
+                    self.set_state(item.id, state, reason);
+                }
+            }
+        }
+        Ok(drift)
+    }
+}
+
+/// 程序退出时停止所有推流，等待推流线程写完封装尾，最多等待指定的时间
+/// 正在运行的推流标记为退出时停止，下次启动时恢复，返回停止的推流数量
+#[derive(Message)]
+#[rtype(result = "usize")]
+pub struct Shutdown(pub Duration);
+
+impl Handler<Shutdown> for MyActor {
+    type Result = ResponseFuture<usize>;
+
+    fn handle(&mut self, msg: Shutdown, _ctx: &mut Context<Self>) -> Self::Result {
+        self.shutting_down = true;
+        // 等待启动的推流下次启动时恢复
+        for (id, _) in std::mem::take(&mut self.pending) {
+            self.set_state(id, IpcState::Shutdown, None);
+        }
+        let count = self.publisher_list.len();
+        for cmd in std::mem::take(&mut self.publisher_list) {
+            let id = cmd.publisher.id;
+            cmd.publisher.stop();
+            self.set_state(id, IpcState::Shutdown, None);
+            self.stopping_list.push(cmd);
+        }
+        // 包括之前手动停止还没有结束的推流
+        let mut finished_list = Vec::new();
+        for cmd in self.stopping_list.iter_mut() {
+            let (finished, rx) = oneshot::channel();
+            cmd.finished.push(finished);
+            finished_list.push(rx);
+        }
+        info!("Stopping {} publishers", finished_list.len());
+        Box::pin(async move {
+            let finished = future::join_all(finished_list);
+            let timeout = clock::delay_for(msg.0);
+            if let Either::Right(_) = future::select(finished, Box::pin(timeout)).await {
+                warn!("Shutdown timed out after {:?}", msg.0);
+            }
+            count
+        })
+    }
+}
+
+/// 获取正在推流的Publisher
+#[derive(Message)]
+#[rtype(result = "Option<Arc<publisher::Publisher>>")]
+pub struct GetPublisher(pub i32);
+
+impl Handler<GetPublisher> for MyActor {
+    type Result = Option<Arc<publisher::Publisher>>;
+
+    fn handle(&mut self, msg: GetPublisher, _ctx: &mut Context<Self>) -> Self::Result {
+        self.get_index(msg.0)
+            .map(|index| Arc::clone(&self.publisher_list[index].publisher))
+    }
+}
+
+/// 获取多个Ipc中正在推流的Publisher
+#[derive(Message)]
+#[rtype(result = "Vec<Arc<publisher::Publisher>>")]
+pub struct GetPublishers(pub Vec<i32>);
+
+impl Handler<GetPublishers> for MyActor {
+    type Result = MessageResult<GetPublishers>;
+
+    fn handle(&mut self, msg: GetPublishers, _ctx: &mut Context<Self>) -> Self::Result {
+        MessageResult(
+            self.publisher_list
+                .iter()
+                .filter(|cmd| msg.0.contains(&cmd.publisher.id))
+                .map(|cmd| Arc::clone(&cmd.publisher))
+                .collect(),
+        )
+    }
+}
+
+/// 推流线程中的状态变化
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetState(
+    pub Arc<publisher::Publisher>,
+    pub IpcState,
+    pub Option<String>,
+);
+
+impl Handler<SetState> for MyActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetState, _ctx: &mut Context<Self>) -> Self::Result {
+        // 已停止的推流不再修改状态
+        let index = match self
+            .publisher_list
+            .iter()
+            .position(|cmd| Arc::ptr_eq(&cmd.publisher, &msg.0))
+        {
+            None => return,
+            Some(index) => index,
+        };
+        // 第一次开始推流或者断开时通知启动命令
+        let started = match msg.1 {
+            IpcState::Streaming => Some(Ok(())),
+            IpcState::Reconnecting => Some(Err(CommandError::Reconnecting(
+                msg.2.clone().unwrap_or_default(),
+            ))),
+            _ => None,
+        };
+        let cmd = &mut self.publisher_list[index];
+        if let Some(result) = started {
+            if let Some(started) = cmd.started.take() {
+                let _ = started.send(result);
+            }
+        }
+        cmd.state = msg.1;
+        cmd.reason = msg.2.clone();
+        if msg.1 == IpcState::Reconnecting {
+            cmd.retry_count += 1;
+            let retry_count = cmd.retry_count;
+            if let Err(e) = self
+                .service
+                .ipc_service
+                .update_retry_count(msg.0.id, retry_count)
+            {
+                error!("{}", e);
+            }
+        }
+        self.set_state(msg.0.id, msg.1, msg.2);
+    }
+}
+
+/// 推流线程中推流地址的状态变化，id 为 0 时是 Ipc 自身的推流地址
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetOutputStatus(
+    pub Arc<publisher::Publisher>,
+    pub i32,
+    pub i32,
+    pub Option<String>,
+);
+
+impl Handler<SetOutputStatus> for MyActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetOutputStatus, _ctx: &mut Context<Self>) -> Self::Result {
+        let SetOutputStatus(publisher, output_id, status, reason) = msg;
+        // 已停止的推流不再修改状态
+        let cmd = match self
+            .publisher_list
+            .iter_mut()
+            .find(|cmd| Arc::ptr_eq(&cmd.publisher, &publisher))
+        {
+            None => return,
+            Some(cmd) => cmd,
+        };
+        let result = if output_id == 0 {
+            cmd.reason = reason.clone();
+            self.service.ipc_service.update_reason(publisher.id, reason)
         } else {
-            info!("id: {} {}", msg.0, msg.1);
+            self.service
+                .output_service
+                .update_status(output_id, status, reason)
+        };
+        if let Err(e) = result {
+            error!("{}", e);
         }
-        msg.0
+    }
+}
+
+/// 推流线程结束，移除并回收线程
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct Finished(pub Arc<publisher::Publisher>, pub Result<(), String>);
+
+impl Handler<Finished> for MyActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: Finished, ctx: &mut Context<Self>) -> Self::Result {
+        // 同一个Ipc停止后可能已经重新启动，按Publisher实例查找
+        let finished = |cmd: &PublisherThread| Arc::ptr_eq(&cmd.publisher, &msg.0);
+        let cmd = match self.publisher_list.iter().position(finished) {
+            Some(index) => {
+                if let Some(started) = self.publisher_list[index].started.take() {
+                    let _ = started.send(msg.1.clone().map_err(CommandError::Failed));
+                }
+                // 只有请求停止后推流才会正常结束，这时已经移到停止列表中，
+                // 仍在推流列表中说明重连次数已用完
+                if let Err(reason) = msg.1 {
+                    self.set_state(msg.0.id, IpcState::Failed, Some(reason));
+                }
+                self.publisher_list.remove(index)
+            }
+            None => match self.stopping_list.iter().position(finished) {
+                Some(index) => self.stopping_list.remove(index),
+                None => return,
+            },
+        };
+        cmd.join();
+        self.start_pending(ctx);
     }
 }
@@ -0,0 +1,331 @@
+use super::Transcoder;
+use log::{info, warn};
+use rusty_ffmpeg::ffi::{
+    av_audio_fifo_alloc, av_audio_fifo_free, av_audio_fifo_read, av_audio_fifo_size,
+    av_audio_fifo_write, av_frame_alloc, av_frame_free, av_frame_get_buffer, av_frame_unref,
+    av_get_channel_layout_nb_channels, av_get_default_channel_layout, av_packet_alloc,
+    av_packet_free, av_packet_unref, av_rescale_q, avcodec_alloc_context3, avcodec_find_decoder,
+    avcodec_find_encoder, avcodec_free_context, avcodec_get_name, avcodec_open2,
+    avcodec_parameters_from_context, avcodec_parameters_to_context, avcodec_receive_frame,
+    avcodec_receive_packet, avcodec_send_frame, avcodec_send_packet, swr_alloc_set_opts,
+    swr_convert_frame, swr_free, swr_init, AVAudioFifo, AVCodecContext, AVCodecID_AV_CODEC_ID_AAC,
+    AVCodecParameters, AVFrame, AVPacket, AVRational,
+    AVSampleFormat_AV_SAMPLE_FMT_FLTP as AV_SAMPLE_FMT_FLTP, AVStream, SwrContext, AVERROR,
+    AVERROR_EOF, AVERROR_INVALIDDATA, AV_CODEC_FLAG_GLOBAL_HEADER, AV_NOPTS_VALUE, EAGAIN,
+};
+use std::ffi::CStr;
+
+/// 音频处理方式，copy 为直接转封装，aac 为转码为 AAC，drop 为丢弃音频
+pub const AUDIO_CODECS: [&str; 3] = ["copy", "aac", "drop"];
+
+/// AAC 编码的采样率，绝大部分 RTMP 服务器和播放器都支持
+const SAMPLE_RATE: i32 = 44100;
+
+/// 每个声道的编码码率，单位 bps
+const BITRATE_PER_CHANNEL: i64 = 64000;
+
+/// 音频转码器，将摄像头的 G.711/PCM 等音频解码、重采样后编码为 AAC
+pub struct AudioTranscoder {
+    dec_ctx: *mut AVCodecContext,
+    enc_ctx: *mut AVCodecContext,
+    swr_ctx: *mut SwrContext,
+    fifo: *mut AVAudioFifo,
+    frame: *mut AVFrame,
+    resampled_frame: *mut AVFrame,
+    enc_frame: *mut AVFrame,
+    enc_pkt: *mut AVPacket,
+    in_time_base: AVRational,
+    // 下一个编码帧的时间戳，使用编码器的时间基
+    next_pts: i64,
+}
+
+impl AudioTranscoder {
+    /// 根据输入音频流创建转码器
+    ///
+    /// # Safety
+    ///
+    /// `in_stream` 必须是已经读取过流信息的有效指针
+    pub unsafe fn new(in_stream: *mut AVStream, global_header: bool) -> Result<Self, String> {
+        let mut transcoder = AudioTranscoder {
+            dec_ctx: std::ptr::null_mut(),
+            enc_ctx: std::ptr::null_mut(),
+            swr_ctx: std::ptr::null_mut(),
+            fifo: std::ptr::null_mut(),
+            frame: std::ptr::null_mut(),
+            resampled_frame: std::ptr::null_mut(),
+            enc_frame: std::ptr::null_mut(),
+            enc_pkt: std::ptr::null_mut(),
+            in_time_base: (*in_stream).time_base,
+            next_pts: AV_NOPTS_VALUE,
+        };
+        // 初始化解码器
+        let in_codecpar = &*(*in_stream).codecpar;
+        let decoder = avcodec_find_decoder(in_codecpar.codec_id);
+        if decoder.is_null() {
+            let codec_name = CStr::from_ptr(avcodec_get_name(in_codecpar.codec_id));
+            return Err(format!(
+                "Decoder {} not found",
+                codec_name.to_string_lossy()
+            ));
+        }
+        transcoder.dec_ctx = avcodec_alloc_context3(decoder);
+        if transcoder.dec_ctx.is_null() {
+            return Err("Failed to allocate decoder context".to_string());
+        }
+        let dec_ctx = &mut *transcoder.dec_ctx;
+        if avcodec_parameters_to_context(dec_ctx, in_codecpar) < 0 {
+            return Err("Failed to copy decoder parameters".to_string());
+        }
+        dec_ctx.pkt_timebase = (*in_stream).time_base;
+        if avcodec_open2(dec_ctx, decoder, std::ptr::null_mut()) < 0 {
+            return Err("Failed to open decoder".to_string());
+        }
+        // G.711 等编码通常没有声道布局信息
+        if dec_ctx.channel_layout == 0 {
+            dec_ctx.channel_layout = av_get_default_channel_layout(dec_ctx.channels) as u64;
+        }
+
+        // 初始化编码器
+        let encoder = avcodec_find_encoder(AVCodecID_AV_CODEC_ID_AAC);
+        if encoder.is_null() {
+            return Err("AAC encoder not found".to_string());
+        }
+        transcoder.enc_ctx = avcodec_alloc_context3(encoder);
+        if transcoder.enc_ctx.is_null() {
+            return Err("Failed to allocate encoder context".to_string());
+        }
+        let enc_ctx = &mut *transcoder.enc_ctx;
+        enc_ctx.channel_layout = dec_ctx.channel_layout;
+        enc_ctx.channels = av_get_channel_layout_nb_channels(enc_ctx.channel_layout);
+        enc_ctx.sample_rate = SAMPLE_RATE;
+        enc_ctx.sample_fmt = if (*encoder).sample_fmts.is_null() {
+            AV_SAMPLE_FMT_FLTP
+        } else {
+            *(*encoder).sample_fmts
+        };
+        enc_ctx.bit_rate = BITRATE_PER_CHANNEL * enc_ctx.channels as i64;
+        enc_ctx.time_base = AVRational {
+            num: 1,
+            den: SAMPLE_RATE,
+        };
+        if global_header {
+            enc_ctx.flags |= AV_CODEC_FLAG_GLOBAL_HEADER as i32;
+        }
+        if avcodec_open2(enc_ctx, encoder, std::ptr::null_mut()) < 0 {
+            return Err("Failed to open AAC encoder".to_string());
+        }
+        info!(
+            "audio transcode {}Hz {}ch -> aac {}Hz {}kbps",
+            dec_ctx.sample_rate,
+            dec_ctx.channels,
+            enc_ctx.sample_rate,
+            enc_ctx.bit_rate / 1000
+        );
+
+        // 初始化重采样
+        transcoder.swr_ctx = swr_alloc_set_opts(
+            std::ptr::null_mut(),
+            enc_ctx.channel_layout as i64,
+            enc_ctx.sample_fmt,
+            enc_ctx.sample_rate,
+            dec_ctx.channel_layout as i64,
+            dec_ctx.sample_fmt,
+            dec_ctx.sample_rate,
+            0,
+            std::ptr::null_mut(),
+        );
+        if transcoder.swr_ctx.is_null() || swr_init(transcoder.swr_ctx) < 0 {
+            return Err("Failed to initialize resampler".to_string());
+        }
+        // AAC 每帧的采样数固定，重采样后的数据先放入缓存再按帧取出
+        transcoder.fifo = av_audio_fifo_alloc(enc_ctx.sample_fmt, enc_ctx.channels, 1);
+        transcoder.frame = av_frame_alloc();
+        transcoder.resampled_frame = av_frame_alloc();
+        transcoder.enc_frame = av_frame_alloc();
+        transcoder.enc_pkt = av_packet_alloc();
+        if transcoder.fifo.is_null()
+            || transcoder.frame.is_null()
+            || transcoder.resampled_frame.is_null()
+            || transcoder.enc_frame.is_null()
+            || transcoder.enc_pkt.is_null()
+        {
+            return Err("Failed to allocate frame".to_string());
+        }
+        Ok(transcoder)
+    }
+
+    /// 设置帧的格式为编码器的输入格式
+    unsafe fn init_frame(&self, frame: *mut AVFrame) {
+        let enc_ctx = &*self.enc_ctx;
+        let frame = &mut *frame;
+        frame.format = enc_ctx.sample_fmt;
+        frame.channel_layout = enc_ctx.channel_layout;
+        frame.channels = enc_ctx.channels;
+        frame.sample_rate = enc_ctx.sample_rate;
+    }
+
+    /// 重采样一帧并放入缓存，`frame` 为空指针时取出重采样器中剩余的数据
+    unsafe fn resample(&mut self, frame: *const AVFrame) -> i32 {
+        self.init_frame(self.resampled_frame);
+        let mut ret = swr_convert_frame(self.swr_ctx, self.resampled_frame, frame);
+        if ret >= 0 {
+            let resampled_frame = &mut *self.resampled_frame;
+            if resampled_frame.nb_samples > 0 {
+                ret = av_audio_fifo_write(
+                    self.fifo,
+                    resampled_frame.data.as_mut_ptr() as *mut *mut std::os::raw::c_void,
+                    resampled_frame.nb_samples,
+                );
+            }
+        }
+        av_frame_unref(self.resampled_frame);
+        ret
+    }
+
+    /// 从缓存中按编码器的帧大小取出数据编码，`flush` 为 true 时把剩余的数据全部编码
+    unsafe fn encode_fifo(
+        &mut self,
+        flush: bool,
+        write: &mut dyn FnMut(*mut AVPacket) -> i32,
+    ) -> i32 {
+        let frame_size = (*self.enc_ctx).frame_size;
+        loop {
+            let size = av_audio_fifo_size(self.fifo);
+            if size == 0 || (size < frame_size && !flush) {
+                return 0;
+            }
+            let nb_samples = if frame_size > 0 {
+                size.min(frame_size)
+            } else {
+                size
+            };
+            self.init_frame(self.enc_frame);
+            let enc_frame = &mut *self.enc_frame;
+            enc_frame.nb_samples = nb_samples;
+            let mut ret = av_frame_get_buffer(enc_frame, 0);
+            if ret < 0 {
+                return ret;
+            }
+            av_audio_fifo_read(
+                self.fifo,
+                enc_frame.data.as_mut_ptr() as *mut *mut std::os::raw::c_void,
+                nb_samples,
+            );
+            enc_frame.pts = self.next_pts;
+            self.next_pts += nb_samples as i64;
+            ret = self.encode(self.enc_frame, write);
+            av_frame_unref(self.enc_frame);
+            if ret < 0 {
+                return ret;
+            }
+        }
+    }
+
+    /// 编码一帧，`frame` 为空指针时刷新编码器
+    unsafe fn encode(
+        &mut self,
+        frame: *mut AVFrame,
+        write: &mut dyn FnMut(*mut AVPacket) -> i32,
+    ) -> i32 {
+        let mut ret = avcodec_send_frame(self.enc_ctx, frame);
+        if ret < 0 && ret != AVERROR_EOF {
+            return ret;
+        }
+        loop {
+            ret = avcodec_receive_packet(self.enc_ctx, self.enc_pkt);
+            if ret == AVERROR(EAGAIN) || ret == AVERROR_EOF {
+                return 0;
+            }
+            if ret < 0 {
+                return ret;
+            }
+            ret = write(self.enc_pkt);
+            av_packet_unref(self.enc_pkt);
+            if ret < 0 {
+                return ret;
+            }
+        }
+    }
+}
+
+impl Transcoder for AudioTranscoder {
+    unsafe fn copy_parameters(&self, codecpar: *mut AVCodecParameters) -> i32 {
+        avcodec_parameters_from_context(codecpar, self.enc_ctx)
+    }
+
+    fn time_base(&self) -> AVRational {
+        unsafe { (*self.enc_ctx).time_base }
+    }
+
+    unsafe fn transcode(
+        &mut self,
+        pkt: *mut AVPacket,
+        write: &mut dyn FnMut(*mut AVPacket) -> i32,
+    ) -> i32 {
+        let mut ret = avcodec_send_packet(self.dec_ctx, pkt);
+        if ret == AVERROR_INVALIDDATA {
+            warn!("Invalid audio packet skipped");
+            return 0;
+        }
+        if ret < 0 && ret != AVERROR_EOF {
+            return ret;
+        }
+        loop {
+            ret = avcodec_receive_frame(self.dec_ctx, self.frame);
+            if ret == AVERROR(EAGAIN) {
+                return 0;
+            }
+            if ret == AVERROR_EOF {
+                ret = self.resample(std::ptr::null());
+                if ret >= 0 {
+                    ret = self.encode_fifo(true, write);
+                }
+                if ret >= 0 {
+                    ret = self.encode(std::ptr::null_mut(), write);
+                }
+                return ret;
+            }
+            if ret < 0 {
+                return ret;
+            }
+            // 以第一帧的时间戳作为起点，之后按采样数累加
+            let pts = (*self.frame).best_effort_timestamp;
+            if self.next_pts == AV_NOPTS_VALUE {
+                self.next_pts = if pts == AV_NOPTS_VALUE {
+                    0
+                } else {
+                    av_rescale_q(pts, self.in_time_base, self.time_base())
+                };
+            }
+            // 重采样器会检查每一帧的声道布局是否与初始化时一致
+            if (*self.frame).channel_layout == 0 {
+                (*self.frame).channel_layout = (*self.dec_ctx).channel_layout;
+            }
+            ret = self.resample(self.frame);
+            av_frame_unref(self.frame);
+            if ret >= 0 {
+                ret = self.encode_fifo(false, write);
+            }
+            if ret < 0 {
+                return ret;
+            }
+        }
+    }
+}
+
+impl Drop for AudioTranscoder {
+    fn drop(&mut self) {
+        unsafe {
+            swr_free(&mut self.swr_ctx);
+            if !self.fifo.is_null() {
+                av_audio_fifo_free(self.fifo);
+            }
+            av_frame_free(&mut self.frame);
+            av_frame_free(&mut self.resampled_frame);
+            av_frame_free(&mut self.enc_frame);
+            av_packet_free(&mut self.enc_pkt);
+            avcodec_free_context(&mut self.dec_ctx);
+            avcodec_free_context(&mut self.enc_ctx);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::hash_map::RandomState;
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, Instant};
+
+/// 断线重连的等待时间，每次失败后加倍，并加入随机抖动避免多路摄像头同时重连
+pub struct Backoff {
+    base: u64,         // 单位毫秒
+    max: u64,          // 单位毫秒
+    max_attempts: u32, // 为 0 时不限制重连次数
+    window: Duration,  // 统计重连次数的时间窗口，为 0 时只统计连续重连的次数
+    pub attempts: u32, // 连续重连的次数
+    failures: VecDeque<Instant>,
+}
+
+impl Backoff {
+    /// `window` 单位秒
+    pub fn new(base: u64, max: u64, max_attempts: u32, window: u64) -> Self {
+        Backoff {
+            base,
+            max,
+            max_attempts,
+            window: Duration::from_secs(window),
+            attempts: 0,
+            failures: VecDeque::new(),
+        }
+    }
+
+    /// 推流恢复正常后重新计算等待时间，没有时间窗口时同时清空重连次数
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+        if self.window.as_secs() == 0 {
+            self.failures.clear();
+        }
+    }
+
+    /// 下一次重连前的等待时间，重连次数用完时返回 None
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        if self.window.as_secs() > 0 {
+            while let Some(time) = self.failures.front() {
+                if now.duration_since(*time) < self.window {
+                    break;
+                }
+                self.failures.pop_front();
+            }
+        }
+        if self.max_attempts > 0 {
+            if self.failures.len() >= self.max_attempts as usize {
+                return None;
+            }
+            self.failures.push_back(now);
+        }
+        let delay = self
+            .base
+            .saturating_mul(1 << self.attempts.min(16))
+            .min(self.max);
+        self.attempts += 1;
+        // 在 delay/2 到 delay 之间随机
+        let jitter = random() % (delay / 2 + 1);
+        Some(Duration::from_millis(delay - jitter))
+    }
+}
+
+/// 每次调用返回不同的随机数，不需要密码学强度
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 抖动后的等待时间在 delay/2 到 delay 之间
+    fn assert_jitter(delay: Duration, expected: u64) {
+        let delay = delay.as_millis() as u64;
+        assert!(
+            delay >= expected - expected / 2 && delay <= expected,
+            "{} not in {}..={}",
+            delay,
+            expected - expected / 2,
+            expected
+        );
+    }
+
+    #[test]
+    fn delay_doubles_until_cap() {
+        let mut backoff = Backoff::new(1000, 10000, 10, 0);
+        for expected in [1000, 2000, 4000, 8000, 10000, 10000].iter() {
+            assert_jitter(backoff.next_delay().unwrap(), *expected);
+        }
+        backoff.reset();
+        assert_jitter(backoff.next_delay().unwrap(), 1000);
+    }
+
+    #[test]
+    fn jitter_stays_in_bounds() {
+        for _ in 0..1000 {
+            let mut backoff = Backoff::new(999, 60000, 0, 0);
+            assert_jitter(backoff.next_delay().unwrap(), 999);
+        }
+    }
+
+    #[test]
+    fn max_attempts_limits_reconnects() {
+        let mut backoff = Backoff::new(1, 1, 3, 0);
+        for _ in 0..3 {
+            assert!(backoff.next_delay().is_some());
+        }
+        assert!(backoff.next_delay().is_none());
+        // 没有时间窗口时，恢复推流后重新计算次数
+        backoff.reset();
+        assert!(backoff.next_delay().is_some());
+    }
+
+    #[test]
+    fn zero_max_attempts_is_unlimited() {
+        let mut backoff = Backoff::new(1, 1, 0, 0);
+        for _ in 0..1000 {
+            assert!(backoff.next_delay().is_some());
+        }
+    }
+
+    #[test]
+    fn window_expires_old_failures() {
+        let mut backoff = Backoff::new(1, 1, 2, 60);
+        assert!(backoff.next_delay().is_some());
+        assert!(backoff.next_delay().is_some());
+        // 有时间窗口时，恢复推流不会清空窗口内的次数
+        backoff.reset();
+        assert!(backoff.next_delay().is_none());
+        // 超出时间窗口的失败不再计数
+        let expired = Instant::now().checked_sub(Duration::from_secs(61)).unwrap();
+        for time in backoff.failures.iter_mut() {
+            *time = expired;
+        }
+        assert!(backoff.next_delay().is_some());
+    }
+}
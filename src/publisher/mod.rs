@@ -0,0 +1,711 @@
+use crate::service::ipc::{Ipc, IpcState};
+use crate::service::output::Output;
+use crate::util;
+use log::{info, warn};
+use rusty_ffmpeg::ffi::{
+    av_dict_free, av_dict_get, av_dict_set, av_dump_format, av_err2str, av_find_best_stream,
+    av_get_media_type_string, av_gettime, av_interleaved_write_frame, av_packet_unref,
+    av_read_frame, av_rescale_q, av_rescale_q_rnd, av_usleep, avcodec_get_name,
+    avformat_alloc_context, avformat_close_input, avformat_find_stream_info, avformat_open_input,
+    AVCodecParameters, AVDictionary, AVFormatContext, AVIOInterruptCB,
+    AVMediaType_AVMEDIA_TYPE_AUDIO as AVMEDIA_TYPE_AUDIO,
+    AVMediaType_AVMEDIA_TYPE_SUBTITLE as AVMEDIA_TYPE_SUBTITLE,
+    AVMediaType_AVMEDIA_TYPE_VIDEO as AVMEDIA_TYPE_VIDEO, AVPacket, AVRational,
+    AVRounding_AV_ROUND_PASS_MINMAX as AV_ROUND_PASS_MINMAX, AVStream, AVERROR_EOF, AVERROR_EXIT,
+    AVERROR_UNKNOWN, AV_DICT_IGNORE_SUFFIX, AV_NOPTS_VALUE, AV_PKT_FLAG_KEY, AV_TIME_BASE,
+};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_int, c_void};
+use std::sync::atomic::Ordering::*;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicI64};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+mod audio;
+mod backoff;
+mod output;
+mod snapshot;
+mod stats;
+mod video;
+
+pub use audio::{AudioTranscoder, AUDIO_CODECS};
+pub use backoff::Backoff;
+use output::{Muxer, StreamSpec};
+pub use output::{
+    OutputFormat, OutputTarget, LIVE_TARGET_ID, RECORD_FORMATS, RECORD_TARGET_ID, STATUS_FAILED,
+    STATUS_IDLE, STATUS_PUSHING, STATUS_RETRYING,
+};
+use snapshot::KeyFrame;
+pub use stats::{Stats, StreamStats};
+pub use video::{VideoTranscoder, VIDEO_CODECS};
+
+/// 解码后重新编码的数据流
+pub trait Transcoder {
+    /// 将编码器参数复制到输出流
+    ///
+    /// # Safety
+    ///
+    /// `codecpar` 必须是有效的输出流参数指针
+    unsafe fn copy_parameters(&self, codecpar: *mut AVCodecParameters) -> i32;
+
+    /// 编码后数据包的时间基
+    fn time_base(&self) -> AVRational;
+
+    /// 解码一个数据包，将重新编码后的数据包交给 `write` 写出
+    /// `pkt` 为空指针时刷新解码器和编码器中缓存的帧
+    ///
+    /// # Safety
+    ///
+    /// `pkt` 必须为空指针或者属于对应输入流的有效数据包
+    unsafe fn transcode(
+        &mut self,
+        pkt: *mut AVPacket,
+        write: &mut dyn FnMut(*mut AVPacket) -> i32,
+    ) -> i32;
+}
+
+fn c_str(s: &str) -> CString {
+    CString::new(s).expect("str to c str")
+}
+
+unsafe fn c_string(s: *const std::os::raw::c_char) -> String {
+    if s.is_null() {
+        String::new()
+    } else {
+        CStr::from_ptr(s).to_string_lossy().to_string()
+    }
+}
+
+/// 将输入流时间基的数据包转换到输出流的时间基后写入封装器
+unsafe fn write_packet(
+    ofmt_ctx_ptr: *mut AVFormatContext,
+    pkt: *mut AVPacket,
+    in_time_base: AVRational,
+    out_index: i32,
+) -> i32 {
+    let out_stream = &*(*(*ofmt_ctx_ptr).streams.offset(out_index as isize));
+    let pkt = &mut *pkt;
+    pkt.stream_index = out_index;
+    pkt.pts = av_rescale_q_rnd(
+        pkt.pts,
+        in_time_base,
+        out_stream.time_base,
+        AV_ROUND_PASS_MINMAX,
+    );
+    pkt.dts = av_rescale_q_rnd(
+        pkt.dts,
+        in_time_base,
+        out_stream.time_base,
+        AV_ROUND_PASS_MINMAX,
+    );
+    pkt.duration = av_rescale_q(pkt.duration, in_time_base, out_stream.time_base);
+    pkt.pos = -1;
+    // log_packet(ofmt_ctx_ptr, &pkt, "out");
+    // 发送到服务器
+    av_interleaved_write_frame(ofmt_ctx_ptr, pkt)
+}
+
+/// 截图时等待视频关键帧的最长时间，单位微秒
+const CAPTURE_TIMEOUT: i64 = 10_000_000;
+
+/// 超时中断时失败原因的前缀
+pub const TIMEOUT_REASON: &str = "timeout";
+
+/// FFmpeg 阻塞读写时定期调用，返回非 0 时中断当前操作
+unsafe extern "C" fn interrupt_callback(opaque: *mut c_void) -> c_int {
+    let publisher = &*(opaque as *const Publisher);
+    publisher.is_interrupted() as c_int
+}
+
+/// RTSP 输入支持的传输方式，对应 FFmpeg 的 rtsp_transport 参数
+pub const RTSP_TRANSPORTS: [&str; 4] = ["tcp", "udp", "udp_multicast", "http"];
+
+pub struct Publisher {
+    pub id: i32,
+    pub exit_code: AtomicI32,
+    stall_timeout: i64,       // 单位微秒，0 为不检测
+    last_activity: AtomicI64, // 最近一次开始读写的时间，单位微秒
+    closing: AtomicBool,      // 停止后写出缓存和封装尾时不再中断
+    key_frame: Mutex<Option<KeyFrame>>,
+    stats: Mutex<Stats>,
+}
+
+impl Publisher {
+    /// `stall_timeout` 为没有收到数据包时中断推流的时间，单位秒
+    pub fn new(id: i32, stall_timeout: u64) -> Self {
+        Publisher {
+            id,
+            exit_code: AtomicI32::new(0),
+            stall_timeout: stall_timeout as i64 * 1_000_000,
+            last_activity: AtomicI64::new(0),
+            closing: AtomicBool::new(false),
+            key_frame: Mutex::new(None),
+            stats: Mutex::new(Stats::default()),
+        }
+    }
+    /// # Safety
+    ///
+    /// `opts` 必须指向一个有效的（可以为空的）`AVDictionary` 指针
+    pub unsafe fn av_dict_set(
+        &self,
+        opts: *mut *mut AVDictionary,
+        key: &str,
+        value: &str,
+        flags: i32,
+    ) -> i32 {
+        av_dict_set(opts, c_str(key).as_ptr(), c_str(value).as_ptr(), flags) as i32
+    }
+
+    /// 安装到输入和输出上下文的中断回调
+    /// 只在 `start` 和 `capture` 执行期间使用，`self` 在此期间不会被释放
+    fn interrupt_callback(&self) -> AVIOInterruptCB {
+        AVIOInterruptCB {
+            callback: Some(interrupt_callback),
+            opaque: self as *const Publisher as *mut c_void,
+        }
+    }
+
+    /// 开始一次阻塞的读写，超时从此时开始计算
+    fn touch(&self) {
+        unsafe {
+            self.last_activity.store(av_gettime(), SeqCst);
+        }
+    }
+
+    /// 请求停止或者一次读写超过了超时时间
+    fn is_interrupted(&self) -> bool {
+        if self.exit_code.load(SeqCst) == 1 && !self.closing.load(SeqCst) {
+            return true;
+        }
+        self.stall_timeout > 0
+            && unsafe { av_gettime() } - self.last_activity.load(SeqCst) > self.stall_timeout
+    }
+
+    /// FFmpeg 返回错误时的失败原因，被超时中断时返回超时原因
+    fn error_reason(&self, ret: i32, target: &str) -> String {
+        if ret == AVERROR_EXIT && self.exit_code.load(SeqCst) != 1 {
+            format!(
+                "{}: {} blocked for more than {} seconds",
+                TIMEOUT_REASON,
+                target,
+                self.stall_timeout / 1_000_000
+            )
+        } else {
+            av_err2str(ret)
+        }
+    }
+
+    /// 分配安装了中断回调的输入上下文
+    unsafe fn alloc_input(&self) -> *mut AVFormatContext {
+        let ctx = avformat_alloc_context();
+        if !ctx.is_null() {
+            (*ctx).interrupt_callback = self.interrupt_callback();
+        }
+        self.touch();
+        ctx
+    }
+
+    /// 打开输入时使用的参数
+    unsafe fn input_options(&self, ipc: &Ipc) -> *mut AVDictionary {
+        let mut opts: *mut AVDictionary = std::ptr::null_mut();
+        // 设置缓存大小，1080p可将值调大
+        self.av_dict_set(&mut opts, "buffer_size", "1024000", 0);
+        // 采集buffer
+        self.av_dict_set(&mut opts, "rtbufsize", "10000", 0);
+        // 设置超时3秒 设置超时断开连接时间，单位微秒
+        self.av_dict_set(&mut opts, "stimeout", "3000000", 0);
+        // 设置最大时延
+        self.av_dict_set(&mut opts, "max_delay", "5000000", 0);
+        // 以 tcp udp udp_multicast http 方式打开
+        if ipc.rtsp.starts_with("rtsp") {
+            self.av_dict_set(&mut opts, "rtsp_transport", &ipc.transport, 0);
+        }
+        // 用户设置的输入参数优先，如4K摄像头需要更大的缓存
+        for (key, value) in ipc.input_options.iter() {
+            self.av_dict_set(&mut opts, key, value, 0);
+        }
+        opts
+    }
+
+    /// 推流的运行统计
+    pub fn stats(&self) -> Stats {
+        let mut stats = self.stats.lock().unwrap().clone();
+        if stats.start_time > 0 {
+            stats.uptime = (util::time::current_timestamp() as i64 - stats.start_time) / 1000;
+        }
+        stats
+    }
+
+    /// 保存最近一个视频关键帧，用于截图
+    unsafe fn keep_key_frame(&self, codecpar: *const AVCodecParameters, pkt: *const AVPacket) {
+        let mut key_frame = self.key_frame.lock().unwrap();
+        let result = match key_frame.as_mut() {
+            Some(key_frame) => key_frame.update(pkt),
+            None => KeyFrame::new(codecpar, pkt).map(|new_key_frame| {
+                *key_frame = Some(new_key_frame);
+            }),
+        };
+        if let Err(msg) = result {
+            warn!("id: {} {}", self.id, msg);
+        }
+    }
+
+    /// 使用推流中最近的视频关键帧截图，还没有读取到关键帧时返回 None
+    pub fn snapshot(&self, width: i32) -> Option<Result<Vec<u8>, String>> {
+        // 只在持有锁时复制关键帧，解码和编码时不阻塞推流线程
+        let key_frame = self.key_frame.lock().unwrap().as_ref()?.try_clone();
+        Some(key_frame.and_then(|key_frame| key_frame.to_jpeg(width)))
+    }
+
+    /// 打开输入读取第一个视频关键帧截图，用于没有推流的 Ipc
+    pub fn capture(&self, ipc: &Ipc, width: i32) -> Result<Vec<u8>, String> {
+        let in_filename = c_str(&ipc.rtsp);
+        unsafe {
+            let mut ifmt_ctx_ptr: *mut AVFormatContext = self.alloc_input();
+            let mut pkt: AVPacket = std::mem::zeroed();
+            let mut opts = self.input_options(ipc);
+            let result = 'outer: {
+                let ret = avformat_open_input(
+                    &mut ifmt_ctx_ptr,
+                    in_filename.as_ptr(),
+                    std::ptr::null_mut(),
+                    &mut opts,
+                );
+                if ret < 0 {
+                    break 'outer Err(self.error_reason(ret, "input"));
+                }
+                let ret = avformat_find_stream_info(ifmt_ctx_ptr, std::ptr::null_mut());
+                if ret < 0 {
+                    break 'outer Err(self.error_reason(ret, "input"));
+                }
+                let video_index = av_find_best_stream(
+                    ifmt_ctx_ptr,
+                    AVMEDIA_TYPE_VIDEO,
+                    -1,
+                    -1,
+                    std::ptr::null_mut(),
+                    0,
+                );
+                if video_index < 0 {
+                    break 'outer Err("No video stream".to_string());
+                }
+                let codecpar = (*(*(*ifmt_ctx_ptr).streams.offset(video_index as isize))).codecpar;
+                let start_time = av_gettime();
+                while av_gettime() - start_time < CAPTURE_TIMEOUT {
+                    self.touch();
+                    let ret = av_read_frame(ifmt_ctx_ptr, &mut pkt);
+                    if ret < 0 {
+                        break 'outer Err(self.error_reason(ret, "input"));
+                    }
+                    if pkt.stream_index == video_index && (pkt.flags & AV_PKT_FLAG_KEY as i32) != 0
+                    {
+                        let key_frame = KeyFrame::new(codecpar, &pkt);
+                        av_packet_unref(&mut pkt);
+                        break 'outer key_frame.and_then(|key_frame| key_frame.to_jpeg(width));
+                    }
+                    av_packet_unref(&mut pkt);
+                }
+                Err("No key frame received".to_string())
+            };
+            av_dict_free(&mut opts);
+            avformat_close_input(&mut ifmt_ctx_ptr);
+            result
+        }
+    }
+
+    /// 打印 FFmpeg 没有使用的参数，一般是参数名写错或者与输入输出协议不匹配
+    unsafe fn log_unused_options(&self, opts: *mut AVDictionary, target: &str) {
+        let empty = c_str("");
+        let mut entry = av_dict_get(
+            opts,
+            empty.as_ptr(),
+            std::ptr::null(),
+            AV_DICT_IGNORE_SUFFIX as i32,
+        );
+        while !entry.is_null() {
+            warn!(
+                "id: {} unused {} option {}={}",
+                self.id,
+                target,
+                CStr::from_ptr((*entry).key).to_string_lossy(),
+                CStr::from_ptr((*entry).value).to_string_lossy()
+            );
+            entry = av_dict_get(opts, empty.as_ptr(), entry, AV_DICT_IGNORE_SUFFIX as i32);
+        }
+    }
+
+    /// 读取一次输入，同时推送到 Ipc 的推流地址、附加推流地址和本地的录像、直播地址
+    /// 每个推流地址的状态变化通过 `on_status(id, status, reason)` 通知，Ipc 的推流地址 id 为 0
+    /// 开始推流时通过 `on_state` 通知
+    fn start(
+        &self,
+        ipc: &Ipc,
+        outputs: &[Output],
+        local_targets: Vec<OutputTarget>,
+        on_status: &(dyn Fn(i32, i32, Option<String>) + Sync),
+        on_state: &dyn Fn(IpcState, Option<String>),
+    ) -> Result<(), String> {
+        let in_file = &ipc.rtsp;
+        let mut muxers: Vec<Muxer> = vec![Muxer::new(OutputTarget::from_ipc(ipc)?)];
+        for output in outputs.iter() {
+            match OutputTarget::from_output(output) {
+                Ok(target) => muxers.push(Muxer::new(target)),
+                Err(msg) => {
+                    warn!("id: {} output {}: {}", self.id, output.id, msg);
+                    on_status(output.id, STATUS_FAILED, Some(msg));
+                }
+            }
+        }
+        muxers.extend(local_targets.into_iter().map(Muxer::new));
+        // 有一个封装器需要全局头时编码器就输出全局头，MPEG-TS 会在关键帧前补上编码参数
+        let global_header = muxers
+            .iter()
+            .any(|muxer| muxer.target.format.global_header());
+        unsafe {
+            let mut ifmt_ctx_ptr: *mut AVFormatContext = self.alloc_input();
+            let mut pkt: AVPacket = std::mem::zeroed();
+            let mut ret;
+            let in_filename = c_str(in_file);
+            let mut opts = self.input_options(ipc);
+            let mut error: Option<String> = None;
+            'outer: {
+                // 打开视频输入
+                ret = avformat_open_input(
+                    &mut ifmt_ctx_ptr,
+                    in_filename.as_ptr(),
+                    std::ptr::null_mut(),
+                    &mut opts,
+                );
+                if ret < 0 {
+                    info!("Could not open input file {:?}", in_filename);
+                    break 'outer;
+                }
+                self.log_unused_options(opts, "input");
+                // 读取视频输入信息
+                ret = avformat_find_stream_info(ifmt_ctx_ptr, std::ptr::null_mut());
+                if ret < 0 {
+                    info!("Failed to retrieve input stream information");
+                    break 'outer;
+                }
+                av_dump_format(ifmt_ctx_ptr, 0, in_filename.as_ptr(), 0);
+                let video_index = av_find_best_stream(
+                    ifmt_ctx_ptr,
+                    AVMEDIA_TYPE_VIDEO,
+                    -1,
+                    -1,
+                    std::ptr::null_mut(),
+                    0,
+                );
+                let ifmt_ctx = &mut *ifmt_ctx_ptr;
+                let in_nb_streams = ifmt_ctx.nb_streams as usize;
+                let in_streams: &[*mut AVStream] =
+                    std::slice::from_raw_parts(ifmt_ctx.streams, in_nb_streams);
+                // 输入流到共用流的映射，-1 表示不输出
+                let mut stream_mapping: Vec<i32> = Vec::with_capacity(in_nb_streams);
+                stream_mapping.resize(stream_mapping.capacity(), -1);
+                let mut specs: Vec<StreamSpec> = Vec::with_capacity(in_nb_streams);
+                let mut stream_stats: Vec<StreamStats> = Vec::with_capacity(in_nb_streams);
+                // 需要转码的音视频流对应的转码器
+                let mut transcoders: Vec<Option<Box<dyn Transcoder>>> =
+                    (0..in_nb_streams).map(|_| None).collect();
+                for (i, in_stream_ptr) in in_streams.iter().enumerate() {
+                    let in_codecpar = &*(**in_stream_ptr).codecpar;
+                    if in_codecpar.codec_type != AVMEDIA_TYPE_AUDIO
+                        && in_codecpar.codec_type != AVMEDIA_TYPE_VIDEO
+                        && in_codecpar.codec_type != AVMEDIA_TYPE_SUBTITLE
+                    {
+                        continue;
+                    }
+                    // 丢弃音频
+                    if ipc.audio_codec == "drop" && in_codecpar.codec_type == AVMEDIA_TYPE_AUDIO {
+                        continue;
+                    }
+                    let transcoder: Option<Result<Box<dyn Transcoder>, String>> = if ipc.video_codec
+                        == "h264"
+                        && in_codecpar.codec_type == AVMEDIA_TYPE_VIDEO
+                    {
+                        Some(
+                            VideoTranscoder::new(ifmt_ctx_ptr, *in_stream_ptr, ipc, global_header)
+                                .map(|t| Box::new(t) as Box<dyn Transcoder>),
+                        )
+                    } else if ipc.audio_codec == "aac"
+                        && in_codecpar.codec_type == AVMEDIA_TYPE_AUDIO
+                    {
+                        Some(
+                            AudioTranscoder::new(*in_stream_ptr, global_header)
+                                .map(|t| Box::new(t) as Box<dyn Transcoder>),
+                        )
+                    } else {
+                        None
+                    };
+                    match transcoder {
+                        None => {}
+                        Some(Err(msg)) => {
+                            info!("{}", msg);
+                            error = Some(msg);
+                            ret = AVERROR_UNKNOWN;
+                            break 'outer;
+                        }
+                        Some(Ok(transcoder)) => transcoders[i] = Some(transcoder),
+                    }
+                    // 转码时使用编码器的参数
+                    let spec = match &transcoders[i] {
+                        Some(transcoder) => StreamSpec::from_transcoder(transcoder.as_ref()),
+                        None => StreamSpec::from_stream(*in_stream_ptr),
+                    };
+                    match spec {
+                        Err(msg) => {
+                            info!("Failed to copy codec parameters: {}", msg);
+                            error = Some(msg);
+                            ret = AVERROR_UNKNOWN;
+                            break 'outer;
+                        }
+                        Ok(spec) => {
+                            stream_mapping[i] = specs.len() as i32;
+                            specs.push(spec);
+                            stream_stats.push(StreamStats::new(
+                                i as i32,
+                                &c_string(av_get_media_type_string(in_codecpar.codec_type)),
+                                &c_string(avcodec_get_name(in_codecpar.codec_id)),
+                            ));
+                        }
+                    }
+                }
+                for muxer in muxers.iter_mut() {
+                    muxer.open(self, &specs);
+                    on_status(muxer.target.id, muxer.status, muxer.reason.clone());
+                }
+                if !muxers.iter().any(Muxer::is_open) {
+                    error = muxers.iter().find_map(|muxer| muxer.reason.clone());
+                    ret = AVERROR_UNKNOWN;
+                    break 'outer;
+                }
+                let mut stats = Stats::new(util::time::current_timestamp() as i64);
+                stats.streams = stream_stats;
+                *self.stats.lock().unwrap() = stats.clone();
+                on_state(IpcState::Streaming, None);
+                let mut cur_pts: [i64; 64] = [0; 64];
+                let start_time = av_gettime();
+                'inner: loop {
+                    let exit_code = self.exit_code.load(SeqCst);
+                    if exit_code == 1 {
+                        info!("stopped.");
+                        break;
+                    }
+                    // 断开的推流地址到时间后重新连接
+                    let now = av_gettime();
+                    for muxer in muxers.iter_mut().filter(|muxer| muxer.can_retry(now)) {
+                        info!("id: {} reopening output {}", self.id, muxer.target.url);
+                        muxer.open(self, &specs);
+                        on_status(muxer.target.id, muxer.status, muxer.reason.clone());
+                    }
+                    // 获取摄像头帧，摄像头不再发送数据时由中断回调超时退出
+                    self.touch();
+                    ret = av_read_frame(ifmt_ctx_ptr, &mut pkt);
+                    if ret < 0 {
+                        break 'inner;
+                    }
+                    let curr_stream_index = pkt.stream_index as usize;
+                    if curr_stream_index >= stream_mapping.len()
+                        || stream_mapping[curr_stream_index] < 0
+                    {
+                        av_packet_unref(&mut pkt);
+                        continue;
+                    }
+                    let spec_index = stream_mapping[curr_stream_index] as usize;
+                    let spec = &specs[spec_index];
+                    let packet_time = util::time::current_timestamp() as i64;
+                    stats.on_input(
+                        spec_index,
+                        pkt.size as usize,
+                        (pkt.flags & AV_PKT_FLAG_KEY as i32) != 0,
+                        packet_time,
+                    );
+                    let orig_pts = pkt.pts;
+                    let orig_duration = pkt.duration;
+                    if orig_pts == AV_NOPTS_VALUE {
+                        pkt.pts = cur_pts[curr_stream_index];
+                        pkt.dts = pkt.pts;
+                    }
+                    if curr_stream_index as i32 == video_index {
+                        if (pkt.flags & AV_PKT_FLAG_KEY as i32) != 0 {
+                            self.keep_key_frame((*in_streams[curr_stream_index]).codecpar, &pkt);
+                        }
+                        let time_base =
+                            (*(*ifmt_ctx.streams.offset(video_index as isize))).time_base;
+                        let time_base_q = AVRational {
+                            num: 1,
+                            den: AV_TIME_BASE as i32,
+                        };
+                        let pts_time = av_rescale_q(pkt.dts, time_base, time_base_q);
+                        let now_time = av_gettime() - start_time;
+                        if pts_time > now_time {
+                            av_usleep((pts_time - now_time) as u32);
+                        }
+                    }
+                    // log_packet(ifmt_ctx_ptr, &pkt, "in");
+                    // 写入所有推流地址，一个推流地址出错时只关闭该地址
+                    let mut dispatch = |out_pkt: *mut AVPacket| {
+                        for muxer in muxers.iter_mut().filter(|muxer| muxer.is_open()) {
+                            self.touch();
+                            let ret = muxer.write(out_pkt, spec_index, spec);
+                            if ret < 0 {
+                                let msg = self.error_reason(ret, "output");
+                                info!(
+                                    "id: {} Error muxing packet to {}: {}",
+                                    self.id, muxer.target.url, msg
+                                );
+                                muxer.fail(msg);
+                                on_status(muxer.target.id, muxer.status, muxer.reason.clone());
+                            }
+                        }
+                        0
+                    };
+                    ret = match transcoders[curr_stream_index].as_mut() {
+                        Some(transcoder) => transcoder.transcode(&mut pkt, &mut dispatch),
+                        /* copy packet */
+                        None => dispatch(&mut pkt),
+                    };
+                    if ret < 0 {
+                        info!("Error transcoding packet");
+                        av_packet_unref(&mut pkt);
+                        break 'inner;
+                    }
+                    if orig_pts == AV_NOPTS_VALUE {
+                        cur_pts[curr_stream_index] += orig_duration;
+                    }
+                    av_packet_unref(&mut pkt);
+                    stats.output_bytes = muxers.iter().map(|muxer| muxer.bytes).sum();
+                    if stats.update_rate(packet_time) {
+                        *self.stats.lock().unwrap() = stats.clone();
+                    }
+                    // 所有推流地址都断开时结束推流，由异常重试重新启动
+                    if !muxers.iter().any(Muxer::is_open) {
+                        error = muxers.iter().find_map(|muxer| muxer.reason.clone());
+                        ret = AVERROR_UNKNOWN;
+                        break 'inner;
+                    }
+                }
+                self.finish();
+                // 写出转码器中缓存的帧
+                for (i, transcoder) in transcoders.iter_mut().enumerate() {
+                    if let Some(transcoder) = transcoder {
+                        let spec_index = stream_mapping[i] as usize;
+                        let spec = &specs[spec_index];
+                        transcoder.transcode(std::ptr::null_mut(), &mut |enc_pkt| {
+                            for muxer in muxers.iter_mut() {
+                                self.touch();
+                                muxer.write(enc_pkt, spec_index, spec);
+                            }
+                            0
+                        });
+                    }
+                }
+            }
+            // 停止时中断读写返回 AVERROR_EXIT，不作为错误处理
+            // 摄像头是直播流，没有请求停止时读到 AVERROR_EOF 说明连接已断开
+            if (ret == AVERROR_EXIT || ret == AVERROR_EOF) && self.exit_code.load(SeqCst) == 1 {
+                info!("stopped.");
+                ret = 0;
+            } else if ret < 0 && error.is_none() {
+                error = Some(self.error_reason(ret, "input"));
+            }
+            self.finish();
+            for muxer in muxers.iter_mut() {
+                if muxer.is_open() {
+                    muxer.close(true);
+                    on_status(muxer.target.id, STATUS_IDLE, None);
+                } else if muxer.status == STATUS_RETRYING {
+                    on_status(muxer.target.id, STATUS_IDLE, muxer.reason.clone());
+                }
+            }
+            av_dict_free(&mut opts);
+            avformat_close_input(&mut ifmt_ctx_ptr);
+            if ret < 0 {
+                info!("Error occurred: {:?}", av_err2str(ret));
+                // std::process::exit(-2);
+                return Err(error.unwrap_or_else(|| av_err2str(ret)));
+            }
+        }
+        Ok(())
+    }
+
+    /// 推流，断开或者输入结束后按 `backoff` 的等待时间原地重连
+    /// 推流状态变化通过 `on_state(state, reason)` 通知，只有请求停止时返回 Ok
+    pub fn run(
+        &self,
+        ipc: &Ipc,
+        outputs: &[Output],
+        local_targets: Vec<OutputTarget>,
+        on_status: &(dyn Fn(i32, i32, Option<String>) + Sync),
+        mut backoff: Backoff,
+        on_state: &dyn Fn(IpcState, Option<String>),
+    ) -> Result<(), String> {
+        loop {
+            let start_time = util::time::current_timestamp() as i64;
+            let result = self.start(ipc, outputs, local_targets.clone(), on_status, on_state);
+            // 只有请求停止时才结束推流，其它情况都按断开处理
+            let reason = match result {
+                Ok(()) if self.exit_code.load(SeqCst) == 1 => return Ok(()),
+                Ok(()) => "Input ended".to_string(),
+                Err(reason) => reason,
+            };
+            if self.exit_code.load(SeqCst) == 1 {
+                return Err(reason);
+            }
+            // 推流过一段时间后才断开，重新计算等待时间
+            if self
+                .stats()
+                .last_packet_time
+                .is_some_and(|time| time >= start_time)
+            {
+                backoff.reset();
+            }
+            let delay = match backoff.next_delay() {
+                None => return Err(reason),
+                Some(delay) => delay,
+            };
+            warn!(
+                "id: {} reconnect {} in {}ms: {}",
+                self.id,
+                backoff.attempts,
+                delay.as_millis(),
+                reason
+            );
+            on_state(IpcState::Reconnecting, Some(reason));
+            if !self.wait(delay) {
+                info!("stopped.");
+                return Ok(());
+            }
+            self.closing.store(false, SeqCst);
+        }
+    }
+
+    /// 等待一段时间，期间请求停止时返回 false
+    fn wait(&self, delay: Duration) -> bool {
+        let deadline = Instant::now() + delay;
+        loop {
+            if self.exit_code.load(SeqCst) == 1 {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            thread::sleep((deadline - now).min(Duration::from_millis(100)));
+        }
+    }
+
+    /// 结束读取输入，之后写出缓存和封装尾时只检测超时
+    fn finish(&self) {
+        self.closing.store(true, SeqCst);
+        self.touch();
+    }
+
+    pub fn stop(&self) -> bool {
+        info!("stoping...");
+        self.exit_code.store(1, SeqCst);
+        true
+    }
+}
@@ -0,0 +1,528 @@
+use super::{c_str, write_packet, Publisher, Transcoder};
+use crate::service::ipc::Ipc;
+use crate::service::output::Output;
+use log::{info, warn};
+use rusty_ffmpeg::ffi::{
+    av_dict_free, av_dump_format, av_err2str, av_gettime, av_guess_format, av_packet_ref,
+    av_packet_unref, av_write_trailer, avcodec_get_name, avcodec_parameters_alloc,
+    avcodec_parameters_copy, avcodec_parameters_free, avformat_alloc_output_context2,
+    avformat_free_context, avformat_new_stream, avformat_query_codec, avformat_write_header,
+    avio_closep, avio_open2, AVCodecParameters, AVDictionary, AVFormatContext,
+    AVMediaType_AVMEDIA_TYPE_SUBTITLE as AVMEDIA_TYPE_SUBTITLE,
+    AVMediaType_AVMEDIA_TYPE_VIDEO as AVMEDIA_TYPE_VIDEO, AVPacket, AVRational, AVStream,
+    AVFMT_GLOBALHEADER, AVFMT_NOFILE, AVIO_FLAG_WRITE, AV_PKT_FLAG_KEY, FF_COMPLIANCE_NORMAL,
+};
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::fs;
+use std::path::Path;
+
+/// 推流地址的状态，对应 tb_ipc_output 的 status 字段
+pub const STATUS_IDLE: i32 = 0;
+pub const STATUS_PUSHING: i32 = 1;
+pub const STATUS_RETRYING: i32 = 2;
+pub const STATUS_FAILED: i32 = 3;
+
+/// 录像分段的封装格式
+pub const RECORD_FORMATS: [&str; 2] = ["ts", "mp4"];
+
+/// 录像的推流地址 id，录像状态不保存到数据库
+pub const RECORD_TARGET_ID: i32 = -1;
+
+/// 本地直播的推流地址 id，直播状态不保存到数据库
+pub const LIVE_TARGET_ID: i32 = -2;
+
+/// 推流地址断开后第一次重试的等待时间，单位微秒，之后每次翻倍
+const RETRY_DELAY: i64 = 5_000_000;
+/// 重试等待时间的上限，单位微秒
+const MAX_RETRY_DELAY: i64 = 60_000_000;
+
+/// 推流输出的封装格式及协议
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// RTMP 推流，FLV 封装
+    Flv,
+    /// RTSP ANNOUNCE 推流
+    Rtsp,
+    /// SRT 推流，MPEG-TS 封装
+    Srt,
+    /// UDP 推流，MPEG-TS 封装
+    Udp,
+    /// 本地分段录像，由 Ipc 的录像设置生成，不能作为推流格式
+    Segment,
+    /// 本地 HLS 直播，由 HTTP 服务提供播放，不能作为推流格式
+    Hls,
+}
+
+impl OutputFormat {
+    /// 根据数据库中保存的名称获取输出格式
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "flv" => Some(OutputFormat::Flv),
+            "rtsp" => Some(OutputFormat::Rtsp),
+            "srt" => Some(OutputFormat::Srt),
+            "udp" => Some(OutputFormat::Udp),
+            _ => None,
+        }
+    }
+
+    /// 对应 FFmpeg 的封装器名称
+    pub fn muxer(&self) -> &'static str {
+        match self {
+            OutputFormat::Flv => "flv",
+            OutputFormat::Rtsp => "rtsp",
+            OutputFormat::Srt | OutputFormat::Udp => "mpegts",
+            OutputFormat::Segment => "segment",
+            OutputFormat::Hls => "hls",
+        }
+    }
+
+    /// 推流地址允许使用的协议
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            OutputFormat::Flv => "rtmp",
+            OutputFormat::Rtsp => "rtsp",
+            OutputFormat::Srt => "srt",
+            OutputFormat::Udp => "udp",
+            OutputFormat::Segment | OutputFormat::Hls => "file",
+        }
+    }
+
+    /// 检查推流地址的协议是否与输出格式匹配
+    pub fn accepts(&self, url: &str) -> bool {
+        let scheme = self.scheme();
+        match self {
+            // rtmps、rtsps 等加密协议同样可用，srt 和 udp 没有对应的加密协议
+            OutputFormat::Flv | OutputFormat::Rtsp => {
+                url.starts_with(&format!("{}://", scheme))
+                    || url.starts_with(&format!("{}s://", scheme))
+            }
+            _ => url.starts_with(&format!("{}://", scheme)),
+        }
+    }
+
+    /// 封装器是否需要把编码参数写在封装头中，而不是每个关键帧前
+    pub fn global_header(&self) -> bool {
+        let name = c_str(self.muxer());
+        unsafe {
+            let ofmt = av_guess_format(name.as_ptr(), std::ptr::null(), std::ptr::null());
+            !ofmt.is_null() && ((*ofmt).flags & AVFMT_GLOBALHEADER as i32) != 0
+        }
+    }
+
+    /// 输出格式对应的默认封装及协议参数
+    fn default_options(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            // 直播流不需要回写时长和文件大小
+            OutputFormat::Flv => &[("flvflags", "no_duration_filesize")],
+            OutputFormat::Rtsp => &[("rtsp_transport", "tcp")],
+            // 每个包最多携带 7 个 TS 包
+            OutputFormat::Srt | OutputFormat::Udp => &[("pkt_size", "1316")],
+            // 文件名中的 %s 替换为分段开始时间的时间戳，每个分段的时间戳从 0 开始
+            OutputFormat::Segment => &[("strftime", "1"), ("reset_timestamps", "1")],
+            // 只保留播放列表中的分段
+            OutputFormat::Hls => &[("hls_flags", "delete_segments+temp_file")],
+        }
+    }
+}
+
+/// 一个推流地址
+#[derive(Debug, Clone)]
+pub struct OutputTarget {
+    /// tb_ipc_output 的 id，tb_ipc 中的推流地址为 0
+    pub id: i32,
+    pub url: String,
+    pub format: OutputFormat,
+    pub options: BTreeMap<String, String>,
+}
+
+impl OutputTarget {
+    fn new(
+        id: i32,
+        url: &str,
+        format_name: &str,
+        options: &BTreeMap<String, String>,
+    ) -> Result<Self, String> {
+        let format = match OutputFormat::from_name(format_name) {
+            None => return Err(format!("unknown output format {}", format_name)),
+            Some(format) => format,
+        };
+        if !format.accepts(url) {
+            return Err(format!("{} is not a {} url", url, format.scheme()));
+        }
+        Ok(OutputTarget {
+            id,
+            url: url.to_string(),
+            format,
+            options: options.clone(),
+        })
+    }
+
+    /// tb_ipc 中的推流地址
+    pub fn from_ipc(ipc: &Ipc) -> Result<Self, String> {
+        OutputTarget::new(0, &ipc.rtmp, &ipc.output_format, &ipc.output_options)
+    }
+
+    /// 录像保存到 `dir` 目录中，按 Ipc 的录像设置分段
+    pub fn record(ipc: &Ipc, dir: &Path) -> Result<Self, String> {
+        let segment_format = match ipc.record_format.as_str() {
+            "ts" => "mpegts",
+            "mp4" => "mp4",
+            _ => return Err(format!("unknown record format {}", ipc.record_format)),
+        };
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let url = dir.join(format!("%s.{}", ipc.record_format));
+        let mut options = BTreeMap::new();
+        options.insert("segment_format".to_string(), segment_format.to_string());
+        options.insert("segment_time".to_string(), ipc.record_segment.to_string());
+        Ok(OutputTarget {
+            id: RECORD_TARGET_ID,
+            url: url.to_string_lossy().to_string(),
+            format: OutputFormat::Segment,
+            options,
+        })
+    }
+
+    /// HLS 直播保存到 `dir` 目录中，启动前清空上一次推流留下的分段
+    pub fn live(dir: &Path, hls_time: u32, hls_list_size: u32) -> Result<Self, String> {
+        if dir.exists() {
+            fs::remove_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let url = dir.join("index.m3u8");
+        let mut options = BTreeMap::new();
+        options.insert("hls_time".to_string(), hls_time.to_string());
+        options.insert("hls_list_size".to_string(), hls_list_size.to_string());
+        Ok(OutputTarget {
+            id: LIVE_TARGET_ID,
+            url: url.to_string_lossy().to_string(),
+            format: OutputFormat::Hls,
+            options,
+        })
+    }
+
+    /// tb_ipc_output 中的推流地址
+    pub fn from_output(output: &Output) -> Result<Self, String> {
+        OutputTarget::new(
+            output.id,
+            &output.url,
+            &output.output_format,
+            &output.output_options,
+        )
+    }
+}
+
+/// 所有推流地址共用的一路音视频流
+pub struct StreamSpec {
+    pub codecpar: *mut AVCodecParameters,
+    /// 写入封装器的数据包的时间基
+    pub time_base: AVRational,
+}
+
+impl StreamSpec {
+    /// 直接转封装的输入流
+    ///
+    /// # Safety
+    ///
+    /// `in_stream` 必须是已打开输入中的有效流
+    pub unsafe fn from_stream(in_stream: *mut AVStream) -> Result<Self, String> {
+        let spec = StreamSpec {
+            codecpar: avcodec_parameters_alloc(),
+            time_base: (*in_stream).time_base,
+        };
+        if spec.codecpar.is_null() {
+            return Err("Could not allocate codec parameters".to_string());
+        }
+        let ret = avcodec_parameters_copy(spec.codecpar, (*in_stream).codecpar);
+        if ret < 0 {
+            return Err(av_err2str(ret));
+        }
+        Ok(spec)
+    }
+
+    /// 转码后的流，使用编码器的参数和时间基
+    ///
+    /// # Safety
+    ///
+    /// 转码器的编码器必须已经打开
+    pub unsafe fn from_transcoder(transcoder: &dyn Transcoder) -> Result<Self, String> {
+        let spec = StreamSpec {
+            codecpar: avcodec_parameters_alloc(),
+            time_base: transcoder.time_base(),
+        };
+        if spec.codecpar.is_null() {
+            return Err("Could not allocate codec parameters".to_string());
+        }
+        let ret = transcoder.copy_parameters(spec.codecpar);
+        if ret < 0 {
+            return Err(av_err2str(ret));
+        }
+        Ok(spec)
+    }
+
+    fn is_video(&self) -> bool {
+        unsafe { (*self.codecpar).codec_type == AVMEDIA_TYPE_VIDEO }
+    }
+}
+
+impl Drop for StreamSpec {
+    fn drop(&mut self) {
+        unsafe {
+            avcodec_parameters_free(&mut self.codecpar);
+        }
+    }
+}
+
+/// 打开推流地址失败的原因
+enum OpenError {
+    /// 封装器不支持流的编码，重试也无法恢复
+    Unsupported(String),
+    /// 网络或服务器错误，稍后重试
+    Io(String),
+}
+
+/// 一个推流地址的封装器，断开后单独重连，不影响其它推流地址
+pub struct Muxer {
+    pub target: OutputTarget,
+    pub status: i32,
+    pub reason: Option<String>,
+    /// 写出的字节数，重连后继续累加
+    pub bytes: u64,
+    ctx: *mut AVFormatContext,
+    /// 共用流的序号到封装器中流序号的映射，-1 表示不输出
+    mapping: Vec<i32>,
+    /// 连续失败的次数
+    failures: u32,
+    /// 下一次重试的时间，单位微秒
+    retry_time: i64,
+    /// 重连后从关键帧开始写入视频
+    waiting_keyframe: bool,
+}
+
+impl Muxer {
+    pub fn new(target: OutputTarget) -> Self {
+        Muxer {
+            target,
+            status: STATUS_IDLE,
+            reason: None,
+            bytes: 0,
+            ctx: std::ptr::null_mut(),
+            mapping: Vec::new(),
+            failures: 0,
+            retry_time: 0,
+            waiting_keyframe: false,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.ctx.is_null()
+    }
+
+    /// 是否到了重试的时间
+    pub fn can_retry(&self, now: i64) -> bool {
+        !self.is_open() && self.status == STATUS_RETRYING && now >= self.retry_time
+    }
+
+    /// 打开推流地址并写入封装头，失败时设置为等待重试或推流失败
+    ///
+    /// # Safety
+    ///
+    /// `specs` 中的编码参数必须有效
+    pub unsafe fn open(&mut self, publisher: &Publisher, specs: &[StreamSpec]) -> bool {
+        match self.try_open(publisher, specs) {
+            Ok(_) => {
+                info!("id: {} output {} opened", publisher.id, self.target.url);
+                self.status = STATUS_PUSHING;
+                self.reason = None;
+                self.failures = 0;
+                self.waiting_keyframe = specs
+                    .iter()
+                    .zip(self.mapping.iter())
+                    .any(|(spec, index)| *index >= 0 && spec.is_video());
+                true
+            }
+            Err(OpenError::Unsupported(msg)) => {
+                info!("id: {} output {}: {}", publisher.id, self.target.url, msg);
+                self.close(false);
+                self.status = STATUS_FAILED;
+                self.reason = Some(msg);
+                false
+            }
+            Err(OpenError::Io(msg)) => {
+                info!("id: {} output {}: {}", publisher.id, self.target.url, msg);
+                self.fail(msg);
+                false
+            }
+        }
+    }
+
+    unsafe fn try_open(
+        &mut self,
+        publisher: &Publisher,
+        specs: &[StreamSpec],
+    ) -> Result<(), OpenError> {
+        let url = c_str(&self.target.url);
+        let format = c_str(self.target.format.muxer());
+        // 初始化输出封装器
+        avformat_alloc_output_context2(
+            &mut self.ctx,
+            std::ptr::null_mut(),
+            format.as_ptr(),
+            url.as_ptr(),
+        );
+        if self.ctx.is_null() {
+            return Err(OpenError::Io("Could not create output context".to_string()));
+        }
+        // 推流服务器不再接收数据时由中断回调超时退出
+        let int_cb = publisher.interrupt_callback();
+        (*self.ctx).interrupt_callback = int_cb;
+        publisher.touch();
+        let ofmt_ptr = (*self.ctx).oformat;
+        self.mapping = vec![-1; specs.len()];
+        let mut stream_index = 0;
+        for (i, spec) in specs.iter().enumerate() {
+            let codecpar = &*spec.codecpar;
+            // 检查封装器是否支持该编码，0 表示不支持，负数表示封装器无法判断
+            let supported =
+                avformat_query_codec(ofmt_ptr, codecpar.codec_id, FF_COMPLIANCE_NORMAL as i32);
+            if supported == 0 {
+                if codecpar.codec_type == AVMEDIA_TYPE_SUBTITLE {
+                    continue;
+                }
+                let codec_name = CStr::from_ptr(avcodec_get_name(codecpar.codec_id));
+                return Err(OpenError::Unsupported(format!(
+                    "{} is not supported by the {} output",
+                    codec_name.to_string_lossy(),
+                    self.target.format.muxer()
+                )));
+            }
+            let out_stream_ptr = avformat_new_stream(self.ctx, std::ptr::null_mut());
+            if out_stream_ptr.is_null() {
+                return Err(OpenError::Io("Failed allocating output stream".to_string()));
+            }
+            let out_stream = &mut *out_stream_ptr;
+            let ret = avcodec_parameters_copy(out_stream.codecpar, spec.codecpar);
+            if ret < 0 {
+                return Err(OpenError::Io(av_err2str(ret)));
+            }
+            out_stream.time_base = spec.time_base;
+            (*out_stream.codecpar).codec_tag = 0;
+            self.mapping[i] = stream_index;
+            stream_index += 1;
+        }
+        av_dump_format(self.ctx, 0, url.as_ptr(), 1);
+        let mut opts: *mut AVDictionary = std::ptr::null_mut();
+        for (key, value) in self.target.format.default_options() {
+            publisher.av_dict_set(&mut opts, key, value, 0);
+        }
+        // 用户设置的输出参数优先
+        for (key, value) in self.target.options.iter() {
+            publisher.av_dict_set(&mut opts, key, value, 0);
+        }
+        let mut ret = 0;
+        if ((*ofmt_ptr).flags & AVFMT_NOFILE as i32) != AVFMT_NOFILE as i32 {
+            // 打开网络流，协议参数会从 opts 中取出
+            ret = avio_open2(
+                &mut (*self.ctx).pb,
+                url.as_ptr(),
+                AVIO_FLAG_WRITE as i32,
+                &int_cb,
+                &mut opts,
+            );
+        }
+        if ret >= 0 {
+            // 写入封装头
+            ret = avformat_write_header(self.ctx, &mut opts);
+        }
+        if ret >= 0 {
+            publisher.log_unused_options(opts, "output");
+        }
+        av_dict_free(&mut opts);
+        if ret < 0 {
+            return Err(OpenError::Io(publisher.error_reason(ret, "output")));
+        }
+        Ok(())
+    }
+
+    /// 复制一份数据包写入封装器，`spec_index` 为数据包所属的共用流
+    ///
+    /// # Safety
+    ///
+    /// `pkt` 必须是有效的数据包，时间基为 `spec.time_base`
+    pub unsafe fn write(
+        &mut self,
+        pkt: *const AVPacket,
+        spec_index: usize,
+        spec: &StreamSpec,
+    ) -> i32 {
+        if self.ctx.is_null() || self.mapping[spec_index] < 0 {
+            return 0;
+        }
+        if self.waiting_keyframe {
+            if !spec.is_video() || ((*pkt).flags & AV_PKT_FLAG_KEY as i32) == 0 {
+                return 0;
+            }
+            self.waiting_keyframe = false;
+        }
+        let size = (*pkt).size as u64;
+        let mut out_pkt: AVPacket = std::mem::zeroed();
+        let mut ret = av_packet_ref(&mut out_pkt, pkt);
+        if ret < 0 {
+            return ret;
+        }
+        ret = write_packet(
+            self.ctx,
+            &mut out_pkt,
+            spec.time_base,
+            self.mapping[spec_index],
+        );
+        av_packet_unref(&mut out_pkt);
+        if ret >= 0 {
+            self.bytes += size;
+        }
+        ret
+    }
+
+    /// 关闭封装器，等待一段时间后重试
+    ///
+    /// # Safety
+    ///
+    /// 封装器不能在其它线程中使用
+    pub unsafe fn fail(&mut self, reason: String) {
+        self.close(false);
+        let delay = (RETRY_DELAY << self.failures.min(4)).min(MAX_RETRY_DELAY);
+        self.failures += 1;
+        self.retry_time = av_gettime() + delay;
+        self.status = STATUS_RETRYING;
+        self.reason = Some(reason);
+        warn!("output {} retry in {}s", self.target.url, delay / 1_000_000);
+    }
+
+    /// 关闭封装器，`write_trailer` 为 true 时先写入封装尾
+    ///
+    /// # Safety
+    ///
+    /// 封装器不能在其它线程中使用
+    pub unsafe fn close(&mut self, write_trailer: bool) {
+        if self.ctx.is_null() {
+            return;
+        }
+        if write_trailer && av_write_trailer(self.ctx) < 0 {
+            info!("Error occurred when closing output {}", self.target.url);
+        }
+        let ofmt_ptr = (*self.ctx).oformat;
+        if ((*ofmt_ptr).flags & AVFMT_NOFILE as i32) != AVFMT_NOFILE as i32 {
+            avio_closep(&mut (*self.ctx).pb);
+        }
+        avformat_free_context(self.ctx);
+        self.ctx = std::ptr::null_mut();
+    }
+}
+
+impl Drop for Muxer {
+    fn drop(&mut self) {
+        unsafe {
+            self.close(false);
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use super::video::output_size;
+use rusty_ffmpeg::ffi::{
+    av_frame_alloc, av_frame_free, av_frame_get_buffer, av_packet_alloc, av_packet_free,
+    av_packet_ref, av_packet_unref, avcodec_alloc_context3, avcodec_find_decoder,
+    avcodec_find_encoder, avcodec_free_context, avcodec_get_name, avcodec_open2,
+    avcodec_parameters_alloc, avcodec_parameters_copy, avcodec_parameters_free,
+    avcodec_parameters_to_context, avcodec_receive_frame, avcodec_receive_packet,
+    avcodec_send_frame, avcodec_send_packet, sws_freeContext, sws_getContext, sws_scale,
+    AVCodecContext, AVCodecID_AV_CODEC_ID_MJPEG, AVCodecParameters, AVFrame, AVPacket,
+    AVPixelFormat_AV_PIX_FMT_YUVJ420P as AV_PIX_FMT_YUVJ420P, AVRational, SwsContext, SWS_BICUBIC,
+};
+use std::ffi::CStr;
+
+/// 最近一个视频关键帧及其编码参数，用于截图
+pub struct KeyFrame {
+    codecpar: *mut AVCodecParameters,
+    pkt: *mut AVPacket,
+}
+
+// 每个实例的数据包和参数都是独立复制的，不会被其它线程修改
+unsafe impl Send for KeyFrame {}
+
+impl KeyFrame {
+    /// 复制视频流的编码参数和关键帧数据包
+    ///
+    /// # Safety
+    ///
+    /// `codecpar` 和 `pkt` 必须是同一视频流的有效指针
+    pub unsafe fn new(
+        codecpar: *const AVCodecParameters,
+        pkt: *const AVPacket,
+    ) -> Result<Self, String> {
+        let mut key_frame = KeyFrame {
+            codecpar: avcodec_parameters_alloc(),
+            pkt: av_packet_alloc(),
+        };
+        if key_frame.codecpar.is_null() || key_frame.pkt.is_null() {
+            return Err("Failed to allocate key frame".to_string());
+        }
+        if avcodec_parameters_copy(key_frame.codecpar, codecpar) < 0 {
+            return Err("Failed to copy codec parameters".to_string());
+        }
+        key_frame.update(pkt)?;
+        Ok(key_frame)
+    }
+
+    /// 替换为新的关键帧
+    ///
+    /// # Safety
+    ///
+    /// `pkt` 必须是与编码参数对应的有效数据包
+    pub unsafe fn update(&mut self, pkt: *const AVPacket) -> Result<(), String> {
+        av_packet_unref(self.pkt);
+        if av_packet_ref(self.pkt, pkt) < 0 {
+            return Err("Failed to copy key frame".to_string());
+        }
+        Ok(())
+    }
+
+    /// 复制关键帧，数据包只增加引用计数，不复制数据
+    pub fn try_clone(&self) -> Result<Self, String> {
+        unsafe { KeyFrame::new(self.codecpar, self.pkt) }
+    }
+
+    /// 解码关键帧后编码为 JPEG，`width` 大于 0 时按原始比例缩放
+    pub fn to_jpeg(&self, width: i32) -> Result<Vec<u8>, String> {
+        unsafe {
+            let mut dec_ctx: *mut AVCodecContext = std::ptr::null_mut();
+            let mut enc_ctx: *mut AVCodecContext = std::ptr::null_mut();
+            let mut sws_ctx: *mut SwsContext = std::ptr::null_mut();
+            let mut frame: *mut AVFrame = av_frame_alloc();
+            let mut scaled_frame: *mut AVFrame = av_frame_alloc();
+            let mut pkt: *mut AVPacket = av_packet_alloc();
+            let result = 'outer: {
+                if frame.is_null() || scaled_frame.is_null() || pkt.is_null() {
+                    break 'outer Err("Failed to allocate frame".to_string());
+                }
+                // 解码关键帧
+                let codec_id = (*self.codecpar).codec_id;
+                let decoder = avcodec_find_decoder(codec_id);
+                if decoder.is_null() {
+                    let codec_name = CStr::from_ptr(avcodec_get_name(codec_id));
+                    break 'outer Err(format!(
+                        "Decoder {} not found",
+                        codec_name.to_string_lossy()
+                    ));
+                }
+                dec_ctx = avcodec_alloc_context3(decoder);
+                if dec_ctx.is_null()
+                    || avcodec_parameters_to_context(dec_ctx, self.codecpar) < 0
+                    || avcodec_open2(dec_ctx, decoder, std::ptr::null_mut()) < 0
+                {
+                    break 'outer Err("Failed to open decoder".to_string());
+                }
+                if avcodec_send_packet(dec_ctx, self.pkt) < 0 {
+                    break 'outer Err("Failed to decode key frame".to_string());
+                }
+                // 刷新解码器，多线程解码时帧会延迟输出
+                avcodec_send_packet(dec_ctx, std::ptr::null());
+                if avcodec_receive_frame(dec_ctx, frame) < 0 {
+                    break 'outer Err("Failed to decode key frame".to_string());
+                }
+
+                // 编码为 JPEG
+                let encoder = avcodec_find_encoder(AVCodecID_AV_CODEC_ID_MJPEG);
+                if encoder.is_null() {
+                    break 'outer Err("MJPEG encoder not found".to_string());
+                }
+                enc_ctx = avcodec_alloc_context3(encoder);
+                if enc_ctx.is_null() {
+                    break 'outer Err("Failed to allocate encoder context".to_string());
+                }
+                let (width, height) = output_size((*frame).width, (*frame).height, width, 0);
+                (*enc_ctx).width = width;
+                (*enc_ctx).height = height;
+                (*enc_ctx).pix_fmt = AV_PIX_FMT_YUVJ420P;
+                (*enc_ctx).time_base = AVRational { num: 1, den: 25 };
+                if avcodec_open2(enc_ctx, encoder, std::ptr::null_mut()) < 0 {
+                    break 'outer Err("Failed to open MJPEG encoder".to_string());
+                }
+                sws_ctx = sws_getContext(
+                    (*frame).width,
+                    (*frame).height,
+                    (*frame).format,
+                    width,
+                    height,
+                    AV_PIX_FMT_YUVJ420P,
+                    SWS_BICUBIC as i32,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    std::ptr::null(),
+                );
+                if sws_ctx.is_null() {
+                    break 'outer Err("Failed to create scale context".to_string());
+                }
+                (*scaled_frame).format = AV_PIX_FMT_YUVJ420P;
+                (*scaled_frame).width = width;
+                (*scaled_frame).height = height;
+                if av_frame_get_buffer(scaled_frame, 0) < 0 {
+                    break 'outer Err("Failed to allocate frame".to_string());
+                }
+                sws_scale(
+                    sws_ctx,
+                    (*frame).data.as_ptr() as *const *const u8,
+                    (*frame).linesize.as_ptr(),
+                    0,
+                    (*frame).height,
+                    (*scaled_frame).data.as_ptr(),
+                    (*scaled_frame).linesize.as_ptr(),
+                );
+                if avcodec_send_frame(enc_ctx, scaled_frame) < 0
+                    || avcodec_receive_packet(enc_ctx, pkt) < 0
+                {
+                    break 'outer Err("Failed to encode JPEG".to_string());
+                }
+                let data = std::slice::from_raw_parts((*pkt).data, (*pkt).size as usize);
+                Ok(data.to_vec())
+            };
+            av_packet_free(&mut pkt);
+            av_frame_free(&mut scaled_frame);
+            av_frame_free(&mut frame);
+            sws_freeContext(sws_ctx);
+            avcodec_free_context(&mut enc_ctx);
+            avcodec_free_context(&mut dec_ctx);
+            result
+        }
+    }
+}
+
+impl Drop for KeyFrame {
+    fn drop(&mut self) {
+        unsafe {
+            av_packet_free(&mut self.pkt);
+            avcodec_parameters_free(&mut self.codecpar);
+        }
+    }
+}
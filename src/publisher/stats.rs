@@ -0,0 +1,102 @@
+use serde::Serialize;
+
+/// 码率和帧率的统计周期，单位毫秒
+const RATE_INTERVAL: i64 = 1000;
+
+/// 一路输入流的统计
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct StreamStats {
+    pub index: i32,
+    pub codec_type: String, // video audio subtitle
+    pub codec: String,
+    pub packets: u64,
+    pub bytes: u64,
+    pub fps: f64,
+    pub bitrate: u64,            // 单位bps
+    pub key_frame_interval: f64, // 最近两个关键帧的间隔，单位秒
+    #[serde(skip)]
+    last_key_frame_time: Option<i64>,
+    #[serde(skip)]
+    rate_packets: u64,
+    #[serde(skip)]
+    rate_bytes: u64,
+}
+
+impl StreamStats {
+    pub fn new(index: i32, codec_type: &str, codec: &str) -> Self {
+        StreamStats {
+            index,
+            codec_type: codec_type.to_string(),
+            codec: codec.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+/// 推流的运行统计，时间单位毫秒
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct Stats {
+    pub start_time: i64,
+    pub uptime: i64, // 单位秒
+    pub last_packet_time: Option<i64>,
+    pub input_bytes: u64,
+    pub output_bytes: u64,  // 推送到多个地址时为所有地址之和
+    pub input_bitrate: u64, // 单位bps
+    pub output_bitrate: u64,
+    pub streams: Vec<StreamStats>,
+    #[serde(skip)]
+    rate_time: i64,
+    #[serde(skip)]
+    rate_input_bytes: u64,
+    #[serde(skip)]
+    rate_output_bytes: u64,
+}
+
+impl Stats {
+    pub fn new(start_time: i64) -> Self {
+        Stats {
+            start_time,
+            rate_time: start_time,
+            ..Default::default()
+        }
+    }
+
+    /// 读取到一个输入数据包，`stream` 为 `streams` 中的序号
+    pub fn on_input(&mut self, stream: usize, size: usize, key_frame: bool, now: i64) {
+        self.input_bytes += size as u64;
+        self.last_packet_time = Some(now);
+        if let Some(stream_stats) = self.streams.get_mut(stream) {
+            stream_stats.packets += 1;
+            stream_stats.bytes += size as u64;
+            if key_frame {
+                if let Some(last) = stream_stats.last_key_frame_time {
+                    stream_stats.key_frame_interval = (now - last) as f64 / 1000.0;
+                }
+                stream_stats.last_key_frame_time = Some(now);
+            }
+        }
+    }
+
+    /// 计算码率和帧率，统计周期结束时返回 true
+    pub fn update_rate(&mut self, now: i64) -> bool {
+        let elapsed = now - self.rate_time;
+        if elapsed < RATE_INTERVAL {
+            return false;
+        }
+        self.input_bitrate = (self.input_bytes - self.rate_input_bytes) * 8 * 1000 / elapsed as u64;
+        self.output_bitrate =
+            (self.output_bytes - self.rate_output_bytes) * 8 * 1000 / elapsed as u64;
+        self.rate_input_bytes = self.input_bytes;
+        self.rate_output_bytes = self.output_bytes;
+        for stream_stats in self.streams.iter_mut() {
+            stream_stats.fps =
+                (stream_stats.packets - stream_stats.rate_packets) as f64 * 1000.0 / elapsed as f64;
+            stream_stats.bitrate =
+                (stream_stats.bytes - stream_stats.rate_bytes) * 8 * 1000 / elapsed as u64;
+            stream_stats.rate_packets = stream_stats.packets;
+            stream_stats.rate_bytes = stream_stats.bytes;
+        }
+        self.rate_time = now;
+        true
+    }
+}
@@ -0,0 +1,349 @@
+use super::{c_str, Transcoder};
+use crate::service::ipc::Ipc;
+use log::{info, warn};
+use rusty_ffmpeg::ffi::{
+    av_dict_free, av_dict_set, av_frame_alloc, av_frame_free, av_frame_get_buffer,
+    av_frame_make_writable, av_frame_unref, av_guess_frame_rate, av_packet_alloc, av_packet_free,
+    av_packet_unref, avcodec_alloc_context3, avcodec_find_decoder, avcodec_find_encoder,
+    avcodec_find_encoder_by_name, avcodec_free_context, avcodec_get_name, avcodec_open2,
+    avcodec_parameters_from_context, avcodec_parameters_to_context, avcodec_receive_frame,
+    avcodec_receive_packet, avcodec_send_frame, avcodec_send_packet, sws_freeContext,
+    sws_getCachedContext, sws_scale, AVCodec, AVCodecContext, AVCodecID_AV_CODEC_ID_H264,
+    AVCodecParameters, AVDictionary, AVFormatContext, AVFrame, AVPacket,
+    AVPictureType_AV_PICTURE_TYPE_NONE as AV_PICTURE_TYPE_NONE,
+    AVPixelFormat_AV_PIX_FMT_YUV420P as AV_PIX_FMT_YUV420P, AVRational, AVStream, SwsContext,
+    AVERROR, AVERROR_EOF, AVERROR_INVALIDDATA, AVERROR_UNKNOWN, AV_CODEC_FLAG_GLOBAL_HEADER,
+    EAGAIN, SWS_BICUBIC,
+};
+use std::ffi::CStr;
+
+/// 视频编码方式，copy 为直接转封装，h264 为解码后重新编码
+pub const VIDEO_CODECS: [&str; 2] = ["copy", "h264"];
+
+/// 没有设置码率时使用的默认码率，单位 kbps
+const DEFAULT_BITRATE: i32 = 2000;
+
+/// 视频转码器，将摄像头的视频（如 H.265）解码后重新编码为 H.264
+pub struct VideoTranscoder {
+    dec_ctx: *mut AVCodecContext,
+    enc_ctx: *mut AVCodecContext,
+    sws_ctx: *mut SwsContext,
+    frame: *mut AVFrame,
+    scaled_frame: *mut AVFrame,
+    enc_pkt: *mut AVPacket,
+}
+
+/// 计算输出分辨率，只设置了宽或高时按原始比例计算另一边，结果取偶数
+pub(super) fn output_size(src_width: i32, src_height: i32, width: i32, height: i32) -> (i32, i32) {
+    if src_width <= 0 || src_height <= 0 {
+        return (width & !1, height & !1);
+    }
+    let (width, height) = match (width > 0, height > 0) {
+        (true, true) => (width, height),
+        (true, false) => (
+            width,
+            (width as i64 * src_height as i64 / src_width as i64) as i32,
+        ),
+        (false, true) => (
+            (height as i64 * src_width as i64 / src_height as i64) as i32,
+            height,
+        ),
+        (false, false) => (src_width, src_height),
+    };
+    (width & !1, height & !1)
+}
+
+/// 优先使用 libx264，没有编译 libx264 时使用 FFmpeg 内置的其它 H.264 编码器
+unsafe fn find_h264_encoder() -> *mut AVCodec {
+    let name = c_str("libx264");
+    let encoder = avcodec_find_encoder_by_name(name.as_ptr());
+    if encoder.is_null() {
+        avcodec_find_encoder(AVCodecID_AV_CODEC_ID_H264)
+    } else {
+        encoder
+    }
+}
+
+impl VideoTranscoder {
+    /// 根据输入视频流和 IPC 的转码参数创建转码器
+    ///
+    /// # Safety
+    ///
+    /// `ifmt_ctx` 和 `in_stream` 必须是已经读取过流信息的有效指针
+    pub unsafe fn new(
+        ifmt_ctx: *mut AVFormatContext,
+        in_stream: *mut AVStream,
+        ipc: &Ipc,
+        global_header: bool,
+    ) -> Result<Self, String> {
+        let mut transcoder = VideoTranscoder {
+            dec_ctx: std::ptr::null_mut(),
+            enc_ctx: std::ptr::null_mut(),
+            sws_ctx: std::ptr::null_mut(),
+            frame: std::ptr::null_mut(),
+            scaled_frame: std::ptr::null_mut(),
+            enc_pkt: std::ptr::null_mut(),
+        };
+        // 初始化解码器
+        let in_codecpar = &*(*in_stream).codecpar;
+        let decoder = avcodec_find_decoder(in_codecpar.codec_id);
+        if decoder.is_null() {
+            let codec_name = CStr::from_ptr(avcodec_get_name(in_codecpar.codec_id));
+            return Err(format!(
+                "Decoder {} not found",
+                codec_name.to_string_lossy()
+            ));
+        }
+        transcoder.dec_ctx = avcodec_alloc_context3(decoder);
+        if transcoder.dec_ctx.is_null() {
+            return Err("Failed to allocate decoder context".to_string());
+        }
+        let dec_ctx = &mut *transcoder.dec_ctx;
+        if avcodec_parameters_to_context(dec_ctx, in_codecpar) < 0 {
+            return Err("Failed to copy decoder parameters".to_string());
+        }
+        dec_ctx.pkt_timebase = (*in_stream).time_base;
+        dec_ctx.framerate = av_guess_frame_rate(ifmt_ctx, in_stream, std::ptr::null_mut());
+        if avcodec_open2(dec_ctx, decoder, std::ptr::null_mut()) < 0 {
+            return Err("Failed to open decoder".to_string());
+        }
+
+        // 初始化编码器
+        let encoder = find_h264_encoder();
+        if encoder.is_null() {
+            return Err("H.264 encoder not found".to_string());
+        }
+        transcoder.enc_ctx = avcodec_alloc_context3(encoder);
+        if transcoder.enc_ctx.is_null() {
+            return Err("Failed to allocate encoder context".to_string());
+        }
+        let enc_ctx = &mut *transcoder.enc_ctx;
+        let (width, height) = output_size(
+            dec_ctx.width,
+            dec_ctx.height,
+            ipc.video_width,
+            ipc.video_height,
+        );
+        enc_ctx.width = width;
+        enc_ctx.height = height;
+        enc_ctx.pix_fmt = AV_PIX_FMT_YUV420P;
+        enc_ctx.sample_aspect_ratio = dec_ctx.sample_aspect_ratio;
+        // 编码器与输入流使用相同的时间基，编码后的数据包可以和转封装的数据包一样处理
+        enc_ctx.time_base = (*in_stream).time_base;
+        let framerate = dec_ctx.framerate;
+        if framerate.num > 0 && framerate.den > 0 {
+            enc_ctx.framerate = framerate;
+        }
+        enc_ctx.gop_size = if ipc.video_gop > 0 {
+            ipc.video_gop
+        } else if framerate.num > 0 && framerate.den > 0 {
+            // 默认2秒一个关键帧
+            (2 * framerate.num + framerate.den - 1) / framerate.den
+        } else {
+            50
+        };
+        // 直播推流不使用 B 帧，降低延时
+        enc_ctx.max_b_frames = 0;
+        let bitrate = if ipc.video_bitrate > 0 {
+            ipc.video_bitrate
+        } else {
+            DEFAULT_BITRATE
+        } as i64
+            * 1000;
+        enc_ctx.bit_rate = bitrate;
+        enc_ctx.rc_max_rate = bitrate;
+        enc_ctx.rc_buffer_size = (bitrate * 2) as i32;
+        if global_header {
+            enc_ctx.flags |= AV_CODEC_FLAG_GLOBAL_HEADER as i32;
+        }
+        let mut opts: *mut AVDictionary = std::ptr::null_mut();
+        if !ipc.video_preset.is_empty() {
+            av_dict_set(
+                &mut opts,
+                c_str("preset").as_ptr(),
+                c_str(&ipc.video_preset).as_ptr(),
+                0,
+            );
+        }
+        av_dict_set(
+            &mut opts,
+            c_str("tune").as_ptr(),
+            c_str("zerolatency").as_ptr(),
+            0,
+        );
+        let ret = avcodec_open2(enc_ctx, encoder, &mut opts);
+        av_dict_free(&mut opts);
+        if ret < 0 {
+            return Err("Failed to open H.264 encoder".to_string());
+        }
+        info!(
+            "video transcode {}x{} -> {}x{} {}kbps gop {}",
+            dec_ctx.width,
+            dec_ctx.height,
+            width,
+            height,
+            bitrate / 1000,
+            enc_ctx.gop_size
+        );
+
+        transcoder.frame = av_frame_alloc();
+        transcoder.scaled_frame = av_frame_alloc();
+        transcoder.enc_pkt = av_packet_alloc();
+        if transcoder.frame.is_null()
+            || transcoder.scaled_frame.is_null()
+            || transcoder.enc_pkt.is_null()
+        {
+            return Err("Failed to allocate frame".to_string());
+        }
+        let scaled_frame = &mut *transcoder.scaled_frame;
+        scaled_frame.format = AV_PIX_FMT_YUV420P;
+        scaled_frame.width = width;
+        scaled_frame.height = height;
+        if av_frame_get_buffer(scaled_frame, 0) < 0 {
+            return Err("Failed to allocate scaled frame".to_string());
+        }
+        Ok(transcoder)
+    }
+
+    /// 判断解码后的帧是否需要缩放或转换像素格式
+    unsafe fn needs_scale(&self) -> bool {
+        let frame = &*self.frame;
+        let enc_ctx = &*self.enc_ctx;
+        frame.width != enc_ctx.width
+            || frame.height != enc_ctx.height
+            || frame.format != enc_ctx.pix_fmt
+    }
+
+    /// 将解码后的帧转换为编码器需要的分辨率和像素格式
+    unsafe fn scale(&mut self) -> i32 {
+        if !self.needs_scale() {
+            return 0;
+        }
+        let frame = &*self.frame;
+        let scaled_frame = &mut *self.scaled_frame;
+        // 摄像头切换分辨率时会重新创建缩放上下文
+        self.sws_ctx = sws_getCachedContext(
+            self.sws_ctx,
+            frame.width,
+            frame.height,
+            frame.format,
+            scaled_frame.width,
+            scaled_frame.height,
+            AV_PIX_FMT_YUV420P,
+            SWS_BICUBIC as i32,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null(),
+        );
+        if self.sws_ctx.is_null() {
+            return AVERROR_UNKNOWN;
+        }
+        // 编码器可能还持有上一帧的数据，写入前确保缓存可写
+        let ret = av_frame_make_writable(scaled_frame);
+        if ret < 0 {
+            return ret;
+        }
+        sws_scale(
+            self.sws_ctx,
+            frame.data.as_ptr() as *const *const u8,
+            frame.linesize.as_ptr(),
+            0,
+            frame.height,
+            scaled_frame.data.as_ptr(),
+            scaled_frame.linesize.as_ptr(),
+        )
+    }
+
+    /// 编码一帧，`frame` 为空指针时刷新编码器
+    unsafe fn encode(
+        &mut self,
+        frame: *mut AVFrame,
+        write: &mut dyn FnMut(*mut AVPacket) -> i32,
+    ) -> i32 {
+        let mut ret = avcodec_send_frame(self.enc_ctx, frame);
+        if ret < 0 && ret != AVERROR_EOF {
+            return ret;
+        }
+        loop {
+            ret = avcodec_receive_packet(self.enc_ctx, self.enc_pkt);
+            if ret == AVERROR(EAGAIN) || ret == AVERROR_EOF {
+                return 0;
+            }
+            if ret < 0 {
+                return ret;
+            }
+            ret = write(self.enc_pkt);
+            av_packet_unref(self.enc_pkt);
+            if ret < 0 {
+                return ret;
+            }
+        }
+    }
+}
+
+impl Transcoder for VideoTranscoder {
+    unsafe fn copy_parameters(&self, codecpar: *mut AVCodecParameters) -> i32 {
+        avcodec_parameters_from_context(codecpar, self.enc_ctx)
+    }
+
+    fn time_base(&self) -> AVRational {
+        unsafe { (*self.enc_ctx).time_base }
+    }
+
+    unsafe fn transcode(
+        &mut self,
+        pkt: *mut AVPacket,
+        write: &mut dyn FnMut(*mut AVPacket) -> i32,
+    ) -> i32 {
+        let mut ret = avcodec_send_packet(self.dec_ctx, pkt);
+        if ret == AVERROR_INVALIDDATA {
+            // 网络丢包导致的坏数据跳过即可，不需要中断推流
+            warn!("Invalid video packet skipped");
+            return 0;
+        }
+        if ret < 0 && ret != AVERROR_EOF {
+            return ret;
+        }
+        loop {
+            ret = avcodec_receive_frame(self.dec_ctx, self.frame);
+            if ret == AVERROR(EAGAIN) {
+                return 0;
+            }
+            if ret == AVERROR_EOF {
+                return self.encode(std::ptr::null_mut(), write);
+            }
+            if ret < 0 {
+                return ret;
+            }
+            let frame = &mut *self.frame;
+            frame.pts = frame.best_effort_timestamp;
+            frame.pict_type = AV_PICTURE_TYPE_NONE;
+            ret = self.scale();
+            if ret >= 0 {
+                ret = if self.needs_scale() {
+                    (*self.scaled_frame).pts = frame.pts;
+                    self.encode(self.scaled_frame, write)
+                } else {
+                    self.encode(self.frame, write)
+                };
+            }
+            av_frame_unref(self.frame);
+            if ret < 0 {
+                return ret;
+            }
+        }
+    }
+}
+
+impl Drop for VideoTranscoder {
+    fn drop(&mut self) {
+        unsafe {
+            sws_freeContext(self.sws_ctx);
+            av_frame_free(&mut self.frame);
+            av_frame_free(&mut self.scaled_frame);
+            av_packet_free(&mut self.enc_pkt);
+            avcodec_free_context(&mut self.dec_ctx);
+            avcodec_free_context(&mut self.enc_ctx);
+        }
+    }
+}
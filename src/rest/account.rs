@@ -24,9 +24,10 @@ pub async fn change_password(
     let old_password = change_password_req.old_password.to_string();
     let new_password = change_password_req.new_password.to_string();
 
+    let token = token.to_str().unwrap().to_string();
     let result = match service
-        .account_service
-        .get_by_token(token.to_str().unwrap().to_string())
+        .block(move |s| s.account_service.get_by_token(token))
+        .await
     {
         Err(e) => serde_json::to_string(&Result::error_description(
             Result::DB_OPERATION_ERROR,
@@ -35,9 +36,11 @@ pub async fn change_password(
         Ok(account) => {
             let db_account = account.unwrap();
             if db_account.password == util::md5::hash_password(old_password) {
+                let password = util::md5::hash_password(new_password);
+                let uid = db_account.uid;
                 match service
-                    .account_service
-                    .change_password(util::md5::hash_password(new_password), db_account.uid)
+                    .block(move |s| s.account_service.change_password(password, uid))
+                    .await
                 {
                     Err(e) => serde_json::to_string(&Result::error_description(
                         Result::DB_OPERATION_ERROR,
@@ -1,4 +1,5 @@
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::pin::Pin;
 use std::rc::Rc;
 use std::task::{Context, Poll};
@@ -6,8 +7,7 @@ use std::task::{Context, Poll};
 use super::super::service;
 use actix_web::body::MessageBody;
 use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
-use actix_web::http::HeaderValue;
-use actix_web::{error, Error};
+use actix_web::{error, web, Error};
 use futures::future::{ok, Ready};
 use futures::Future;
 use log::info;
@@ -62,13 +62,24 @@ where
         Box::pin(async move {
             let path = req.path().to_string();
             if path == "/api/login" || path == "/" || path.starts_with("/admin") {
-                return Ok(svr.call(req).await?);
+                return svr.call(req).await;
             }
-            let value = HeaderValue::from_str("").unwrap();
-            let token = req.headers().get("token").unwrap_or(&value);
-            if token.len() > 0 {
-                let token = token.to_str().unwrap().to_string();
-                match db_service_clone.account_service.get_by_token(token.clone()) {
+            // 播放器无法设置请求头，直播地址可以通过 ?token= 传入，其它接口只认请求头
+            let is_live = path.starts_with("/api/ipc/") && path.contains("/live/");
+            let token = match req.headers().get("token") {
+                Some(token) => token.to_str().unwrap_or_default().to_string(),
+                None if is_live => {
+                    web::Query::<HashMap<String, String>>::from_query(req.query_string())
+                        .ok()
+                        .and_then(|query| query.get("token").cloned())
+                        .unwrap_or_default()
+                }
+                None => String::new(),
+            };
+            if !token.is_empty() {
+                let account =
+                    db_service_clone.block(move |s| s.account_service.get_by_token(token));
+                match account.await {
                     Err(e) => {
                         info!("{}", &e.to_string());
                         Err(error::ErrorUnauthorized("Unauthorized"))
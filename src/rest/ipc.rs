@@ -1,17 +1,19 @@
 use actix::prelude::*;
+use actix_web::error::BlockingError;
 use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
 
+use log::warn;
 use serde::{Deserialize, Serialize};
 
-use async_std::task;
-
 use crate::my_actor;
+use crate::publisher;
+use crate::publisher::{OutputFormat, AUDIO_CODECS, RECORD_FORMATS, RTSP_TRANSPORTS, VIDEO_CODECS};
 use crate::result::Page;
 use crate::result::Result;
 use crate::service;
 use crate::service::ipc;
 use crate::util;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 
 #[derive(Serialize, Deserialize)]
@@ -21,6 +23,147 @@ pub struct IpcInfoReq {
     pub name: String,
     pub rtsp: String,
     pub rtmp: String,
+    pub output_format: Option<String>,
+    pub transport: Option<String>,
+    pub input_options: Option<BTreeMap<String, String>>,
+    pub output_options: Option<BTreeMap<String, String>>,
+    pub video_codec: Option<String>,
+    pub video_bitrate: Option<i32>,
+    pub video_gop: Option<i32>,
+    pub video_preset: Option<String>,
+    pub video_width: Option<i32>,
+    pub video_height: Option<i32>,
+    pub audio_codec: Option<String>,
+    pub record: Option<i32>,
+    pub record_format: Option<String>,
+    pub record_segment: Option<i32>,
+    pub live: Option<i32>,
+    pub retry_max_attempts: Option<i32>,
+    pub retry_delay: Option<i64>,
+    pub retry_max_delay: Option<i64>,
+    pub retry_window: Option<i64>,
+    pub tag: Option<String>,  // 传空字符串时清除标签
+    pub version: Option<i32>, // 修改时传入读取到的版本号，已被其它请求修改时返回 409
+}
+
+impl IpcInfoReq {
+    /// 校验请求参数并写入到Ipc中，未传的可选参数保留原值
+    /// 校验失败时返回出错的参数名
+    fn apply_to(&self, ipc: &mut ipc::Ipc) -> std::result::Result<(), &'static str> {
+        let output_format = self.output_format.as_deref().unwrap_or(&ipc.output_format);
+        match OutputFormat::from_name(output_format) {
+            None => return Err("output_format"),
+            Some(format) => {
+                if !format.accepts(&self.rtmp) {
+                    return Err("rtmp");
+                }
+            }
+        }
+        let transport = self.transport.as_deref().unwrap_or(&ipc.transport);
+        if !RTSP_TRANSPORTS.contains(&transport) {
+            return Err("transport");
+        }
+        let video_codec = self.video_codec.as_deref().unwrap_or(&ipc.video_codec);
+        if !VIDEO_CODECS.contains(&video_codec) {
+            return Err("video_codec");
+        }
+        let audio_codec = self.audio_codec.as_deref().unwrap_or(&ipc.audio_codec);
+        if !AUDIO_CODECS.contains(&audio_codec) {
+            return Err("audio_codec");
+        }
+        let record = self.record.unwrap_or(ipc.record);
+        if record != 0 && record != 1 {
+            return Err("record");
+        }
+        let live = self.live.unwrap_or(ipc.live);
+        if live != 0 && live != 1 {
+            return Err("live");
+        }
+        let record_format = self.record_format.as_deref().unwrap_or(&ipc.record_format);
+        if !RECORD_FORMATS.contains(&record_format) {
+            return Err("record_format");
+        }
+        let record_segment = self.record_segment.unwrap_or(ipc.record_segment);
+        if record_segment <= 0 {
+            return Err("record_segment");
+        }
+        let video_params = [
+            (self.video_bitrate, "video_bitrate"),
+            (self.video_gop, "video_gop"),
+            (self.video_width, "video_width"),
+            (self.video_height, "video_height"),
+        ];
+        for (value, field) in video_params.iter() {
+            if value.unwrap_or_default() < 0 {
+                return Err(field);
+            }
+        }
+        // 重连参数不传时使用配置文件中的设置
+        let retry_max_attempts = self.retry_max_attempts.or(ipc.retry_max_attempts);
+        if retry_max_attempts.unwrap_or_default() < 0 {
+            return Err("retry_max_attempts");
+        }
+        let retry_delay = self.retry_delay.or(ipc.retry_delay);
+        if retry_delay.is_some_and(|v| v <= 0) {
+            return Err("retry_delay");
+        }
+        let retry_max_delay = self.retry_max_delay.or(ipc.retry_max_delay);
+        if retry_max_delay.is_some_and(|v| v <= 0 || v < retry_delay.unwrap_or_default()) {
+            return Err("retry_max_delay");
+        }
+        let retry_window = self.retry_window.or(ipc.retry_window);
+        if retry_window.unwrap_or_default() < 0 {
+            return Err("retry_window");
+        }
+        if let Some(tag) = &self.tag {
+            if tag.chars().count() > 50 {
+                return Err("tag");
+            }
+            ipc.tag = if tag.is_empty() {
+                None
+            } else {
+                Some(tag.to_string())
+            };
+        }
+        ipc.retry_max_attempts = retry_max_attempts;
+        ipc.retry_delay = retry_delay;
+        ipc.retry_max_delay = retry_max_delay;
+        ipc.retry_window = retry_window;
+        ipc.record = record;
+        ipc.live = live;
+        ipc.record_format = record_format.to_string();
+        ipc.record_segment = record_segment;
+        ipc.video_codec = video_codec.to_string();
+        ipc.audio_codec = audio_codec.to_string();
+        ipc.video_bitrate = self.video_bitrate.unwrap_or(ipc.video_bitrate);
+        ipc.video_gop = self.video_gop.unwrap_or(ipc.video_gop);
+        ipc.video_width = self.video_width.unwrap_or(ipc.video_width);
+        ipc.video_height = self.video_height.unwrap_or(ipc.video_height);
+        if let Some(video_preset) = &self.video_preset {
+            ipc.video_preset = video_preset.to_string();
+        }
+        ipc.output_format = output_format.to_string();
+        ipc.transport = transport.to_string();
+        ipc.key = self.key.to_string();
+        ipc.name = self.name.to_string();
+        ipc.rtsp = self.rtsp.to_string();
+        ipc.rtmp = self.rtmp.to_string();
+        if let Some(input_options) = &self.input_options {
+            ipc.input_options = input_options.clone();
+        }
+        if let Some(output_options) = &self.output_options {
+            ipc.output_options = output_options.clone();
+        }
+        Ok(())
+    }
+}
+
+/// Ipc列表中的一行，正在推流时带上运行统计
+#[derive(Serialize)]
+pub struct IpcInfoResp {
+    #[serde(flatten)]
+    pub ipc: ipc::Ipc,
+    pub stats: Option<publisher::Stats>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -28,6 +171,50 @@ pub struct PagingInfoReq {
     pub page: Option<u32>,
     pub rows: Option<u32>,
     pub keyword: Option<String>,
+    pub state: Option<String>,
+    pub tag: Option<String>,
+    pub error: Option<bool>, // true 只查询有失败原因的Ipc，false 只查询没有的
+    pub start_time: Option<i64>, // 创建时间范围，单位毫秒
+    pub end_time: Option<i64>,
+    pub sort: Option<String>, // id key name state create_time update_time state_time
+    pub order: Option<String>, // asc desc
+}
+
+/// 每页最多返回的行数
+const MAX_PAGE_ROWS: u32 = 1000;
+
+impl PagingInfoReq {
+    /// 校验查询参数，校验失败时返回出错的参数名
+    fn to_query(&self) -> std::result::Result<ipc::IpcQuery, &'static str> {
+        let state = match self.state.as_deref() {
+            None => None,
+            Some(name) => Some(ipc::IpcState::from_name(name).ok_or("state")?),
+        };
+        let sort = match self.sort.as_deref() {
+            None => ipc::IpcSort::default(),
+            Some(name) => ipc::IpcSort::from_name(name).ok_or("sort")?,
+        };
+        let desc = match self.order.as_deref() {
+            None | Some("asc") => false,
+            Some("desc") => true,
+            Some(_) => return Err("order"),
+        };
+        if let (Some(start_time), Some(end_time)) = (self.start_time, self.end_time) {
+            if start_time > end_time {
+                return Err("end_time");
+            }
+        }
+        Ok(ipc::IpcQuery {
+            keyword: self.keyword.clone().filter(|keyword| !keyword.is_empty()),
+            state,
+            tag: self.tag.clone(),
+            has_error: self.error,
+            start_time: self.start_time,
+            end_time: self.end_time,
+            sort,
+            desc,
+        })
+    }
 }
 
 #[post("/api/ipc")]
@@ -37,21 +224,16 @@ pub async fn add_ipc(
 ) -> impl Responder {
     let create_time = util::time::current_timestamp();
 
-    let ipc = ipc::Ipc::new(
-        0,
-        ipc_info_req.key.to_string(),
-        ipc_info_req.name.to_string(),
-        ipc_info_req.rtsp.to_string(),
-        ipc_info_req.rtmp.to_string(),
-        0,
-        None,
-        0,
-        create_time as i64,
-        None,
-    );
-    let result = match service.ipc_service.insert(ipc) {
-        Ok(_) => Result::success(),
-        Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
+    let mut ipc = ipc::Ipc {
+        create_time: create_time as i64,
+        ..Default::default()
+    };
+    let result = match ipc_info_req.apply_to(&mut ipc) {
+        Err(field) => Result::error_description(Result::INVALID_PARAMETER, field),
+        Ok(_) => match service.block(move |s| s.ipc_service.insert(ipc)).await {
+            Ok(_) => Result::success(),
+            Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
+        },
     };
 
     HttpResponse::Ok()
@@ -59,28 +241,47 @@ pub async fn add_ipc(
         .body(serde_json::to_string(&result).unwrap())
 }
 
+/// 修改Ipc，正在推流时使用新的配置重启推流
+/// 版本号不一致时返回 409，需要重新读取后再修改
 #[put("/api/ipc")]
 pub async fn update_ipc(
     service: web::Data<Arc<service::Service>>,
+    addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
     ipc_info_req: web::Json<IpcInfoReq>,
 ) -> impl Responder {
+    let mut conflict = false;
     let result = match ipc_info_req.id {
         None => Result::error_description(Result::INVALID_PARAMETER, "id"),
-        Some(id) => match service.ipc_service.get(id) {
+        Some(id) => match service.block(move |s| s.ipc_service.get(id)).await {
             Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
             Ok(ipc) => match ipc.clone() {
                 None => Result::error(Result::DATA_NOT_FOUND),
+                Some(db_ipc) if ipc_info_req.version.is_some_and(|v| v != db_ipc.version) => {
+                    conflict = true;
+                    Result::error(Result::VERSION_CONFLICT)
+                }
                 Some(mut db_ipc) => {
-                    if db_ipc.enable == 1 {
-                        Result::error(Result::ALREADY_PUSHING)
+                    if let Err(field) = ipc_info_req.apply_to(&mut db_ipc) {
+                        Result::error_description(Result::INVALID_PARAMETER, field)
                     } else {
                         let update_time = util::time::current_timestamp();
-                        db_ipc.key = ipc_info_req.key.to_string();
-                        db_ipc.name = ipc_info_req.name.to_string();
-                        db_ipc.rtsp = ipc_info_req.rtsp.to_string();
-                        db_ipc.rtmp = ipc_info_req.rtmp.to_string();
                         db_ipc.update_time = Some(update_time as i64);
-                        match service.ipc_service.update(db_ipc) {
+                        let active = db_ipc.state.is_active();
+                        // 读取之后被其它请求修改时不覆盖
+                        match service
+                            .block(move |s| s.ipc_service.update_config(db_ipc))
+                            .await
+                        {
+                            Ok(0) => {
+                                conflict = true;
+                                Result::error(Result::VERSION_CONFLICT)
+                            }
+                            Ok(_) if active => match addr.send(my_actor::Restart(id)).await {
+                                Ok(result) => command_result(result),
+                                Err(e) => {
+                                    Result::error_description(Result::PUSH_ERROR, &e.to_string())
+                                }
+                            },
                             Ok(_) => Result::success(),
                             Err(e) => Result::error_description(
                                 Result::DB_OPERATION_ERROR,
@@ -92,32 +293,114 @@ pub async fn update_ipc(
             },
         },
     };
+    let mut response = if conflict {
+        HttpResponse::Conflict()
+    } else {
+        HttpResponse::Ok()
+    };
+    response
+        .content_type("application/json")
+        .body(serde_json::to_string(&result).unwrap())
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SnapshotReq {
+    pub width: Option<i32>,
+}
+
+/// 截图，正在推流时使用推流中最近的关键帧，否则打开摄像头读取一个关键帧
+/// `width` 大于 0 时按原始比例缩放，可用于列表缩略图
+#[get("/api/ipc/{id}/snapshot")]
+pub async fn get_snapshot(
+    service: web::Data<Arc<service::Service>>,
+    addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
+    id: web::Path<i32>,
+    web::Query(snapshot_req): web::Query<SnapshotReq>,
+) -> HttpResponse {
+    let id = id.0;
+    let width = snapshot_req.width.unwrap_or_default();
+    let result = if width < 0 {
+        Result::error_description(Result::INVALID_PARAMETER, "width")
+    } else {
+        match service.block(move |s| s.ipc_service.get(id)).await {
+            Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
+            Ok(None) => Result::error(Result::DATA_NOT_FOUND),
+            Ok(Some(db_ipc)) => {
+                let running = addr.send(my_actor::GetPublisher(id)).await.unwrap_or(None);
+                let stall_timeout = service.publisher_config.stall_timeout;
+                // 解码和打开摄像头比较耗时，放到线程池中执行
+                let jpeg =
+                    web::block(
+                        move || match running.as_ref().and_then(|p| p.snapshot(width)) {
+                            Some(jpeg) => jpeg,
+                            None => {
+                                publisher::Publisher::new(id, stall_timeout).capture(&db_ipc, width)
+                            }
+                        },
+                    )
+                    .await;
+                match jpeg {
+                    Ok(jpeg) => {
+                        return HttpResponse::Ok()
+                            .content_type("image/jpeg")
+                            .header("Cache-Control", "no-cache")
+                            .body(jpeg)
+                    }
+                    Err(BlockingError::Error(e)) => {
+                        Result::error_description(Result::SNAPSHOT_ERROR, &e)
+                    }
+                    Err(BlockingError::Canceled) => Result::error(Result::SNAPSHOT_ERROR),
+                }
+            }
+        }
+    };
     HttpResponse::Ok()
         .content_type("application/json")
         .body(serde_json::to_string(&result).unwrap())
 }
 
+/// 推流命令的执行结果
+pub(super) fn command_result(
+    result: std::result::Result<(), my_actor::CommandError>,
+) -> Result<()> {
+    match result {
+        Ok(_) => Result::success(),
+        Err(my_actor::CommandError::NotFound) => Result::error(Result::DATA_NOT_FOUND),
+        Err(my_actor::CommandError::AlreadyRunning) => Result::error(Result::ALREADY_PUSHING),
+        Err(my_actor::CommandError::NotRunning) => Result::error(Result::NOT_PUSHING),
+        Err(my_actor::CommandError::Db(e)) => {
+            Result::error_description(Result::DB_OPERATION_ERROR, &e)
+        }
+        Err(my_actor::CommandError::Failed(e)) => Result::error_description(Result::PUSH_ERROR, &e),
+        Err(my_actor::CommandError::Reconnecting(e)) => {
+            Result::error_description(Result::RECONNECTING, &e)
+        }
+    }
+}
+
+/// 启动推流，开始推流或者启动失败后返回
 #[get("/api/ipc/{id}/start")]
 pub async fn ipc_publish_start(
     service: web::Data<Arc<service::Service>>,
     addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
     id: web::Path<i32>,
 ) -> impl Responder {
-    let result = match service.ipc_service.get(id.0) {
+    let id = id.0;
+    let result = match service.block(move |s| s.ipc_service.get(id)).await {
         Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
         Ok(ipc) => match ipc.clone() {
             None => Result::error(Result::DATA_NOT_FOUND),
-            Some(mut db_ipc) => {
-                if db_ipc.enable == 1 {
+            Some(db_ipc) => {
+                if db_ipc.state.is_active() {
                     Result::error(Result::ALREADY_PUSHING)
                 } else {
-                    db_ipc.enable = 1;
-                    task::spawn(addr.send(my_actor::Ping(db_ipc.id, db_ipc.enable)));
-                    let update_time = util::time::current_timestamp();
-                    db_ipc.reason = None;
-                    db_ipc.update_time = Some(update_time as i64);
-                    match service.ipc_service.update(db_ipc) {
-                        Ok(_) => Result::success(),
+                    // 手动启动时清零重连次数，失败状态的Ipc也可以重新启动，推流状态由Actor修改
+                    let update = service.block(move |s| s.ipc_service.update_retry_count(id, 0));
+                    match update.await {
+                        Ok(_) => match addr.send(my_actor::Start(db_ipc.id)).await {
+                            Ok(result) => command_result(result),
+                            Err(e) => Result::error_description(Result::PUSH_ERROR, &e.to_string()),
+                        },
                         Err(e) => {
                             Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string())
                         }
@@ -131,57 +414,114 @@ pub async fn ipc_publish_start(
         .body(serde_json::to_string(&result).unwrap())
 }
 
+/// 停止推流，推流线程结束后返回
 #[get("/api/ipc/{id}/stop")]
 pub async fn ipc_publish_stop(
-    service: web::Data<Arc<service::Service>>,
     addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
     id: web::Path<i32>,
 ) -> impl Responder {
-    let result = match service.ipc_service.get(id.0) {
-        Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
-        Ok(ipc) => match ipc.clone() {
-            None => Result::error(Result::DATA_NOT_FOUND),
-            Some(mut db_ipc) => {
-                if db_ipc.enable == 0 {
-                    Result::error(Result::NOT_PUSHING)
-                } else {
-                    db_ipc.enable = 0;
-                    db_ipc.retry_count = 0;
-                    db_ipc.reason = None;
-                    task::spawn(addr.send(my_actor::Ping(db_ipc.id, db_ipc.enable)));
-                    let update_time = util::time::current_timestamp();
-                    db_ipc.update_time = Some(update_time as i64);
-                    match service.ipc_service.update(db_ipc) {
-                        Ok(_) => Result::success(),
-                        Err(e) => {
-                            Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string())
-                        }
-                    }
-                }
-            }
-        },
+    let result = match addr.send(my_actor::Stop(id.0)).await {
+        Ok(result) => command_result(result),
+        Err(e) => Result::error_description(Result::PUSH_ERROR, &e.to_string()),
     };
     HttpResponse::Ok()
         .content_type("application/json")
         .body(serde_json::to_string(&result).unwrap())
 }
 
+/// 重启推流，没有推流时直接启动
+#[get("/api/ipc/{id}/restart")]
+pub async fn ipc_publish_restart(
+    addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
+    id: web::Path<i32>,
+) -> impl Responder {
+    let result = match addr.send(my_actor::Restart(id.0)).await {
+        Ok(result) => command_result(result),
+        Err(e) => Result::error_description(Result::PUSH_ERROR, &e.to_string()),
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&result).unwrap())
+}
+
+/// 推流状态，正在推流时包括运行统计
+#[get("/api/ipc/{id}/status")]
+pub async fn get_ipc_status(
+    addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
+    id: web::Path<i32>,
+) -> impl Responder {
+    let result = match addr.send(my_actor::QueryStatus(id.0)).await {
+        Ok(Ok(status)) => serde_json::to_string(&Result::success_return_data(status)),
+        Ok(Err(my_actor::CommandError::NotFound)) => {
+            serde_json::to_string(&Result::error(Result::DATA_NOT_FOUND))
+        }
+        Ok(Err(e)) => serde_json::to_string(&Result::error_description(
+            Result::DB_OPERATION_ERROR,
+            &e.to_string(),
+        )),
+        Err(e) => serde_json::to_string(&Result::error_description(
+            Result::PUSH_ERROR,
+            &e.to_string(),
+        )),
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(result.unwrap())
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct DeleteIpcReq {
+    pub stop: Option<bool>, // 为 true 时先停止正在进行的推流
+}
+
+/// 删除Ipc的录像和HLS直播目录，数据已经删除，失败时只记录日志
+async fn remove_files(service: &service::Service, id: i32) {
+    let record_service = service.record_service.clone();
+    let live_service = service.live_service.clone();
+    let removed = web::block(move || {
+        record_service.remove(id)?;
+        live_service.remove(id)
+    })
+    .await;
+    if let Err(e) = removed {
+        warn!("Remove files of ipc {} failed: {}", id, e);
+    }
+}
+
 #[delete("/api/ipc/{id}")]
 pub async fn delete_ipc(
     service: web::Data<Arc<service::Service>>,
+    addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
     id: web::Path<i32>,
+    web::Query(delete_req): web::Query<DeleteIpcReq>,
 ) -> impl Responder {
     let id = id.0;
-    let result = match service.ipc_service.get(id) {
+    let result = match service.block(move |s| s.ipc_service.get(id)).await {
         Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
         Ok(ipc) => match ipc.clone() {
             None => Result::error(Result::DATA_NOT_FOUND),
             Some(db_ipc) => {
-                if db_ipc.enable == 1 {
-                    Result::error(Result::ALREADY_PUSHING)
+                let stop_result = if !db_ipc.state.is_active() {
+                    Ok(())
+                } else if delete_req.stop.unwrap_or(false) {
+                    match addr.send(my_actor::Stop(id)).await {
+                        Ok(Err(my_actor::CommandError::NotRunning)) => Ok(()),
+                        Ok(result) => result,
+                        Err(e) => Err(my_actor::CommandError::Failed(e.to_string())),
+                    }
                 } else {
-                    match service.ipc_service.delete(id) {
-                        Ok(_) => Result::success(),
+                    Err(my_actor::CommandError::AlreadyRunning)
+                };
+                if stop_result.is_err() {
+                    command_result(stop_result)
+                } else {
+                    // 同时删除附加的推流地址
+                    let delete = service.block(move |s| s.ipc_service.delete_with_outputs(id));
+                    match delete.await {
+                        Ok(_) => {
+                            remove_files(&service, id).await;
+                            Result::success()
+                        }
                         Err(e) => {
                             Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string())
                         }
@@ -202,7 +542,7 @@ pub async fn get_ipc(
     id: web::Path<i32>,
 ) -> impl Responder {
     let id = id.0;
-    let result = match service.ipc_service.get(id) {
+    let result = match service.block(move |s| s.ipc_service.get(id)).await {
         Err(e) => serde_json::to_string(&Result::error_description(
             Result::DB_OPERATION_ERROR,
             &e.to_string(),
@@ -215,27 +555,61 @@ pub async fn get_ipc(
         .body(result.unwrap())
 }
 
+#[get("/api/ipc/{id}/stats")]
+pub async fn get_ipc_stats(
+    addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
+    id: web::Path<i32>,
+) -> impl Responder {
+    let result = match addr.send(my_actor::GetPublisher(id.0)).await {
+        Ok(Some(running)) => serde_json::to_string(&Result::success_return_data(running.stats())),
+        _ => serde_json::to_string(&Result::error(Result::NOT_PUSHING)),
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(result.unwrap())
+}
+
 #[get("/api/ipcs")]
 pub async fn get_ipc_list(
     service: web::Data<Arc<service::Service>>,
+    addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
     web::Query(paging): web::Query<PagingInfoReq>,
 ) -> impl Responder {
-    let page = match paging.page {
-        None => 1,
-        Some(page) => page,
-    };
-    let rows = match paging.rows {
-        None => 10,
-        Some(rows) => rows,
+    let page = paging.page.unwrap_or(1);
+    let rows = paging.rows.unwrap_or(10);
+    let query = if page == 0 {
+        Err("page")
+    } else if rows == 0 || rows > MAX_PAGE_ROWS {
+        Err("rows")
+    } else {
+        paging.to_query()
     };
-    let result = match service.ipc_service.count() {
-        Err(e) => serde_json::to_string(&Result::error_description(
-            Result::DB_OPERATION_ERROR,
-            &e.to_string(),
-        )),
-        Ok(total) => match service.ipc_service.get_list(page, rows, paging.keyword) {
-            Ok(ipc_list) => {
-                let page = Page::new(total, ipc_list);
+    let result = match query {
+        Err(field) => {
+            serde_json::to_string(&Result::error_description(Result::INVALID_PARAMETER, field))
+        }
+        Ok(query) => match service
+            .block(move |s| s.ipc_service.search(&query, page, rows))
+            .await
+        {
+            Ok((total, ipc_list)) => {
+                let ids = ipc_list.iter().map(|ipc| ipc.id).collect();
+                let running = addr
+                    .send(my_actor::GetPublishers(ids))
+                    .await
+                    .unwrap_or_default();
+                let rows: Vec<IpcInfoResp> = ipc_list
+                    .into_iter()
+                    .map(|ipc| IpcInfoResp {
+                        stats: running
+                            .iter()
+                            .find(|publisher| publisher.id == ipc.id)
+                            .map(|publisher| publisher.stats()),
+                        ipc,
+                    })
+                    .collect();
+                let page = Page::new(total, rows);
                 serde_json::to_string(&Result::success_return_data(page))
             }
             Err(e) => serde_json::to_string(&Result::error_description(
@@ -251,20 +625,16 @@ pub async fn get_ipc_list(
 
 #[get("/api/ipcs/num")]
 pub async fn get_ip_num(service: web::Data<Arc<service::Service>>) -> impl Responder {
-    let total = match service.ipc_service.count() {
-        Err(_) => 0,
-        Ok(total) => total,
-    };
-
-    let enable_num = match service.ipc_service.count_enable() {
-        Err(_) => 0,
-        Ok(num) => num,
-    };
-
-    let reason_num = match service.ipc_service.count_reason() {
-        Err(_) => 0,
-        Ok(num) => num,
-    };
+    let (total, enable_num, reason_num) = service
+        .block(|s| {
+            Ok((
+                s.ipc_service.count().unwrap_or_default(),
+                s.ipc_service.count_active().unwrap_or_default(),
+                s.ipc_service.count_failed().unwrap_or_default(),
+            ))
+        })
+        .await
+        .unwrap_or_default();
 
     let mut map: HashMap<&str, u64> = HashMap::new();
     map.insert("total", total);
@@ -278,10 +648,11 @@ pub async fn get_ip_num(service: web::Data<Arc<service::Service>>) -> impl Respo
 
 #[get("/api/ipc/key/gen")]
 pub async fn gen_key(service: web::Data<Arc<service::Service>>) -> impl Responder {
-    let total = match service.ipc_service.count() {
-        Err(_) => 0,
-        Ok(total) => total,
-    } + 1;
+    let total = service
+        .block(|s| s.ipc_service.count())
+        .await
+        .unwrap_or_default()
+        + 1;
 
     let key = format!("D{:04X}", total);
 
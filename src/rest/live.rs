@@ -0,0 +1,54 @@
+use actix_web::{get, web, HttpResponse};
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+
+use serde::{Deserialize, Serialize};
+
+use crate::service;
+use std::sync::Arc;
+
+#[derive(Serialize, Deserialize)]
+pub struct TokenReq {
+    pub token: Option<String>,
+}
+
+/// HLS直播的播放列表和分段，只在启用直播的Ipc推流时存在
+/// 通过 ?token= 认证时，播放列表中的分段地址带上同样的 token
+#[get("/api/ipc/{id}/live/{name}")]
+pub async fn get_live(
+    service: web::Data<Arc<service::Service>>,
+    path: web::Path<(i32, String)>,
+    web::Query(token_req): web::Query<TokenReq>,
+) -> HttpResponse {
+    let (id, name) = path.into_inner();
+    let live_service = service.live_service.clone();
+    let file_name = name.clone();
+    // 读取文件放到线程池中执行
+    let data = match web::block(move || live_service.read(id, &file_name)).await {
+        Err(_) => return HttpResponse::NotFound().finish(),
+        Ok(data) => data,
+    };
+    if !name.ends_with(".m3u8") {
+        return HttpResponse::Ok().content_type("video/mp2t").body(data);
+    }
+    let body = match token_req.token {
+        None => data,
+        Some(token) => {
+            let token = utf8_percent_encode(&token, NON_ALPHANUMERIC).to_string();
+            String::from_utf8_lossy(&data)
+                .lines()
+                .map(|line| {
+                    if line.is_empty() || line.starts_with('#') {
+                        format!("{}\n", line)
+                    } else {
+                        format!("{}?token={}\n", line, token)
+                    }
+                })
+                .collect::<String>()
+                .into_bytes()
+        }
+    };
+    HttpResponse::Ok()
+        .content_type("application/vnd.apple.mpegurl")
+        .header("Cache-Control", "no-cache")
+        .body(body)
+}
@@ -26,7 +26,8 @@ pub async fn login(
 ) -> impl Responder {
     let username = login_info_req.username.to_string();
     let password = login_info_req.password.to_string();
-    let result = match service.account_service.get_by_username(username) {
+    let account = service.block(move |s| s.account_service.get_by_username(username));
+    let result = match account.await {
         Err(e) => serde_json::to_string(&Result::error_description(
             Result::DB_OPERATION_ERROR,
             &e.to_string(),
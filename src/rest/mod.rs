@@ -2,7 +2,12 @@ mod account;
 mod auth;
 mod index;
 mod ipc;
+mod live;
 mod login;
+mod output;
+mod record;
+mod runtime;
+mod scheduler;
 mod server;
 
 pub use server::*;
@@ -0,0 +1,183 @@
+use actix::prelude::*;
+use actix_web::{delete, get, post, put, web, HttpResponse, Responder};
+
+use serde::{Deserialize, Serialize};
+
+use super::ipc::command_result;
+use crate::my_actor;
+use crate::publisher::OutputFormat;
+use crate::result::Result;
+use crate::service;
+use crate::service::output;
+use crate::util;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+#[derive(Serialize, Deserialize)]
+pub struct OutputInfoReq {
+    pub id: Option<i32>,
+    pub url: String,
+    pub output_format: Option<String>,
+    pub output_options: Option<BTreeMap<String, String>>,
+    pub enable: Option<i32>,
+}
+
+impl OutputInfoReq {
+    /// 校验请求参数并写入到Output中，未传的可选参数保留原值
+    /// 校验失败时返回出错的参数名
+    fn apply_to(&self, output: &mut output::Output) -> std::result::Result<(), &'static str> {
+        let output_format = self
+            .output_format
+            .as_deref()
+            .unwrap_or(&output.output_format);
+        match OutputFormat::from_name(output_format) {
+            None => return Err("output_format"),
+            Some(format) => {
+                if !format.accepts(&self.url) {
+                    return Err("url");
+                }
+            }
+        }
+        let enable = self.enable.unwrap_or(output.enable);
+        if enable != 0 && enable != 1 {
+            return Err("enable");
+        }
+        output.output_format = output_format.to_string();
+        output.url = self.url.to_string();
+        output.enable = enable;
+        if let Some(output_options) = &self.output_options {
+            output.output_options = output_options.clone();
+        }
+        Ok(())
+    }
+}
+
+/// 推流地址修改后，Ipc正在推流时重启推流使修改生效
+async fn restart_active(
+    service: &Arc<service::Service>,
+    addr: &Addr<my_actor::MyActor>,
+    ipc_id: i32,
+) -> Result<()> {
+    match service.block(move |s| s.ipc_service.get(ipc_id)).await {
+        Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
+        Ok(Some(ipc)) if ipc.state.is_active() => {
+            match addr.send(my_actor::Restart(ipc_id)).await {
+                Ok(result) => command_result(result),
+                Err(e) => Result::error_description(Result::PUSH_ERROR, &e.to_string()),
+            }
+        }
+        Ok(_) => Result::success(),
+    }
+}
+
+#[post("/api/ipc/{id}/output")]
+pub async fn add_output(
+    service: web::Data<Arc<service::Service>>,
+    addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
+    id: web::Path<i32>,
+    output_info_req: web::Json<OutputInfoReq>,
+) -> impl Responder {
+    let ipc_id = id.0;
+    let result = match service.block(move |s| s.ipc_service.get(ipc_id)).await {
+        Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
+        Ok(None) => Result::error(Result::DATA_NOT_FOUND),
+        Ok(Some(_)) => {
+            let create_time = util::time::current_timestamp();
+            let mut output = output::Output {
+                ipc_id,
+                create_time: create_time as i64,
+                ..Default::default()
+            };
+            match output_info_req.apply_to(&mut output) {
+                Err(field) => Result::error_description(Result::INVALID_PARAMETER, field),
+                Ok(_) => match service
+                    .block(move |s| s.output_service.insert(output))
+                    .await
+                {
+                    Ok(_) => restart_active(&service, &addr, ipc_id).await,
+                    Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
+                },
+            }
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&result).unwrap())
+}
+
+#[put("/api/ipc/output")]
+pub async fn update_output(
+    service: web::Data<Arc<service::Service>>,
+    addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
+    output_info_req: web::Json<OutputInfoReq>,
+) -> impl Responder {
+    let result = match output_info_req.id {
+        None => Result::error_description(Result::INVALID_PARAMETER, "id"),
+        Some(id) => match service.block(move |s| s.output_service.get(id)).await {
+            Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
+            Ok(None) => Result::error(Result::DATA_NOT_FOUND),
+            Ok(Some(mut db_output)) => {
+                if let Err(field) = output_info_req.apply_to(&mut db_output) {
+                    Result::error_description(Result::INVALID_PARAMETER, field)
+                } else {
+                    let update_time = util::time::current_timestamp();
+                    db_output.update_time = Some(update_time as i64);
+                    let ipc_id = db_output.ipc_id;
+                    match service
+                        .block(move |s| s.output_service.update(db_output))
+                        .await
+                    {
+                        Ok(_) => restart_active(&service, &addr, ipc_id).await,
+                        Err(e) => {
+                            Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string())
+                        }
+                    }
+                }
+            }
+        },
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&result).unwrap())
+}
+
+#[delete("/api/ipc/output/{id}")]
+pub async fn delete_output(
+    service: web::Data<Arc<service::Service>>,
+    addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
+    id: web::Path<i32>,
+) -> impl Responder {
+    let id = id.0;
+    let result = match service.block(move |s| s.output_service.get(id)).await {
+        Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
+        Ok(None) => Result::error(Result::DATA_NOT_FOUND),
+        Ok(Some(db_output)) => match service.block(move |s| s.output_service.delete(id)).await {
+            Ok(_) => restart_active(&service, &addr, db_output.ipc_id).await,
+            Err(e) => Result::error_description(Result::DB_OPERATION_ERROR, &e.to_string()),
+        },
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&result).unwrap())
+}
+
+#[get("/api/ipc/{id}/outputs")]
+pub async fn get_output_list(
+    service: web::Data<Arc<service::Service>>,
+    id: web::Path<i32>,
+) -> impl Responder {
+    let id = id.0;
+    let result = match service.block(move |s| s.output_service.get_list(id)).await {
+        Err(e) => serde_json::to_string(&Result::error_description(
+            Result::DB_OPERATION_ERROR,
+            &e.to_string(),
+        )),
+        Ok(output_list) => serde_json::to_string(&Result::success_return_data(output_list)),
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(result.unwrap())
+}
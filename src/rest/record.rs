@@ -0,0 +1,92 @@
+use actix_web::error::BlockingError;
+use actix_web::{get, web, HttpResponse, Responder};
+use futures::StreamExt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::result::Result;
+use crate::service::{self, record::Segment};
+use std::io;
+use std::sync::Arc;
+
+/// 录像的时间段，单位毫秒，不传时不限制
+#[derive(Serialize, Deserialize)]
+pub struct TimeRangeReq {
+    pub start: Option<i64>,
+    pub end: Option<i64>,
+}
+
+/// 读取录像目录比较耗时，放到线程池中执行
+async fn list_segments(
+    service: &service::Service,
+    id: i32,
+    range: TimeRangeReq,
+) -> std::result::Result<Vec<Segment>, BlockingError<io::Error>> {
+    let record_service = service.record_service.clone();
+    web::block(move || record_service.list(id, range.start, range.end)).await
+}
+
+#[get("/api/ipc/{id}/recordings")]
+pub async fn get_recording_list(
+    service: web::Data<Arc<service::Service>>,
+    id: web::Path<i32>,
+    web::Query(range): web::Query<TimeRangeReq>,
+) -> impl Responder {
+    let result = match list_segments(&service, id.0, range).await {
+        Err(e) => serde_json::to_string(&Result::error_description(
+            Result::FILE_OPERATION_ERROR,
+            &e.to_string(),
+        )),
+        Ok(segments) => serde_json::to_string(&Result::success_return_data(segments)),
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(result.unwrap())
+}
+
+/// 下载时间段内的录像，TS 分段按顺序拼接为一个文件，MP4 分段只能单个下载
+#[get("/api/ipc/{id}/recordings/download")]
+pub async fn download_recordings(
+    service: web::Data<Arc<service::Service>>,
+    id: web::Path<i32>,
+    web::Query(range): web::Query<TimeRangeReq>,
+) -> HttpResponse {
+    let id = id.0;
+    let result = match list_segments(&service, id, range).await {
+        Err(e) => Result::error_description(Result::FILE_OPERATION_ERROR, &e.to_string()),
+        Ok(segments) => {
+            let is_ts = segments.iter().all(|segment| segment.name.ends_with(".ts"));
+            match segments.first() {
+                None => Result::error(Result::DATA_NOT_FOUND),
+                Some(_) if segments.len() > 1 && !is_ts => {
+                    Result::error_description(Result::INVALID_PARAMETER, "end")
+                }
+                Some(first) => {
+                    let (content_type, extension) = if is_ts {
+                        ("video/mp2t", "ts")
+                    } else {
+                        ("video/mp4", "mp4")
+                    };
+                    let filename = format!("{}-{}.{}", id, first.start_time, extension);
+                    let paths: Vec<_> = segments.into_iter().map(|segment| segment.path).collect();
+                    // 逐个读取分段，避免把整个时间段的录像读入内存
+                    let body = futures::stream::iter(paths).then(|path| async move {
+                        async_std::fs::read(path).await.map(web::Bytes::from)
+                    });
+                    return HttpResponse::Ok()
+                        .content_type(content_type)
+                        .header(
+                            "Content-Disposition",
+                            format!("attachment; filename=\"{}\"", filename),
+                        )
+                        .streaming(Box::pin(body));
+                }
+            }
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(serde_json::to_string(&result).unwrap())
+}
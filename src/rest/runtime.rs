@@ -0,0 +1,46 @@
+use actix::prelude::*;
+use actix_web::{get, post, web, HttpResponse, Responder};
+
+use crate::my_actor;
+use crate::result::Result;
+use std::sync::Arc;
+
+/// 当前进程中运行的推流，以及数据库中推流状态不一致的Ipc
+#[get("/api/runtime/publishers")]
+pub async fn get_runtime_publishers(
+    addr: web::Data<Arc<Addr<my_actor::MyActor>>>,
+) -> impl Responder {
+    let result = match addr.send(my_actor::GetRuntime).await {
+        Ok(Ok(runtime)) => serde_json::to_string(&Result::success_return_data(runtime)),
+        Ok(Err(e)) => serde_json::to_string(&Result::error_description(
+            Result::DB_OPERATION_ERROR,
+            &e.to_string(),
+        )),
+        Err(e) => serde_json::to_string(&Result::error_description(
+            Result::PUSH_ERROR,
+            &e.to_string(),
+        )),
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(result.unwrap())
+}
+
+/// 修复推流状态不一致的Ipc，返回修复前的差异
+#[post("/api/runtime/reconcile")]
+pub async fn reconcile_runtime(addr: web::Data<Arc<Addr<my_actor::MyActor>>>) -> impl Responder {
+    let result = match addr.send(my_actor::Reconcile).await {
+        Ok(Ok(drift)) => serde_json::to_string(&Result::success_return_data(drift)),
+        Ok(Err(e)) => serde_json::to_string(&Result::error_description(
+            Result::DB_OPERATION_ERROR,
+            &e.to_string(),
+        )),
+        Err(e) => serde_json::to_string(&Result::error_description(
+            Result::PUSH_ERROR,
+            &e.to_string(),
+        )),
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(result.unwrap())
+}
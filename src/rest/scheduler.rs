@@ -0,0 +1,21 @@
+use actix::prelude::*;
+use actix_web::{get, web, HttpResponse, Responder};
+
+use crate::result::Result;
+use crate::scheduler;
+use std::sync::Arc;
+
+/// 定时任务最近一次的执行情况
+#[get("/api/jobs")]
+pub async fn get_jobs(addr: web::Data<Arc<Addr<scheduler::Scheduler>>>) -> impl Responder {
+    let result = match addr.send(scheduler::GetJobs).await {
+        Ok(jobs) => serde_json::to_string(&Result::success_return_data(jobs)),
+        Err(e) => serde_json::to_string(&Result::error_description(
+            Result::PUSH_ERROR,
+            &e.to_string(),
+        )),
+    };
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(result.unwrap())
+}
@@ -7,18 +7,30 @@ use super::account;
 use super::auth;
 use super::index;
 use super::ipc;
+use super::live;
 use super::login;
+use super::output;
+use super::record;
+use super::runtime;
+use super::scheduler;
 use crate::config::Config;
 use crate::my_actor;
+use crate::scheduler::Scheduler;
 use crate::service;
-use async_std::task;
 use std::io;
 use std::sync::Arc;
+use std::time::Duration;
 
-use log::info;
+use log::{error, info, warn};
 
 pub struct Server {}
 
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Server {
     pub fn new() -> Self {
         Server {}
@@ -26,37 +38,47 @@ impl Server {
 
     pub async fn start(&self) -> io::Result<()> {
         let config = Config::new();
+        for deprecated in config.deprecated.iter() {
+            warn!("{}", deprecated);
+        }
         let bind = format!("{}:{}", config.http.host, config.http.port);
 
         info!("Listening on http://{}", bind);
 
-        let addr = my_actor::MyActor::new().start();
-        let addr_arc = Arc::new(addr);
-
         let service = service::Service::new();
         let service_arc = Arc::new(service);
 
-        // 启动上一次非正常结束的推流任务
-        task::spawn(service::start::start_undone(
-            service_arc.clone(),
-            addr_arc.clone(),
-        ));
-        // 定时检查 异常终止任务，并重试
-        task::spawn(service::start::retry_abnormal(
-            config,
-            service_arc.clone(),
-            addr_arc.clone(),
-        ));
+        // 推流Actor和HTTP接口共用数据库连接池
+        let addr = my_actor::MyActor::new(service_arc.clone()).start();
+        let addr_arc = Arc::new(addr);
 
-        // 定时检查 状态检查
-        task::spawn(service::start::status_check(service_arc.clone()));
+        let undone_service = service_arc.clone();
+        let undone_addr = addr_arc.clone();
+        let prune_service = service_arc.clone();
+        let scheduler = Scheduler::new()
+            // 恢复上一次非正常结束或者退出时停止的推流任务
+            .job_async("start_undone", 0, 0, move || {
+                let service = undone_service.clone();
+                let addr = Addr::clone(&undone_addr);
+                Box::pin(service::start::start_undone(service, addr))
+            })
+            // 定时清理过期录像
+            .job("record_prune", 0, config.record.interval_time, move || {
+                service::start::record_prune(&prune_service)
+            })
+            .start_in_thread();
+        let scheduler_arc = Arc::new(scheduler);
+        let shutdown_addr = addr_arc.clone();
+        let shutdown_timeout = Duration::from_secs(config.publisher.shutdown_timeout);
 
+        // 收到 SIGTERM 或 Ctrl-C 时停止接收请求，HTTP 服务结束后返回
         HttpServer::new(move || {
             App::new()
                 .wrap(CookieSession::signed(&[0; 32]).secure(false))
                 .wrap(auth::Auth(service_arc.clone()))
                 .data(service_arc.clone())
                 .data(addr_arc.clone())
+                .data(scheduler_arc.clone())
                 .service(index::hello)
                 .service(login::login)
                 .service(ipc::add_ipc)
@@ -66,13 +88,38 @@ impl Server {
                 .service(ipc::get_ipc)
                 .service(ipc::ipc_publish_start)
                 .service(ipc::ipc_publish_stop)
+                .service(ipc::ipc_publish_restart)
+                .service(ipc::get_ipc_status)
+                .service(runtime::get_runtime_publishers)
+                .service(runtime::reconcile_runtime)
+                .service(scheduler::get_jobs)
                 .service(ipc::get_ip_num)
                 .service(ipc::gen_key)
+                .service(ipc::get_snapshot)
+                .service(ipc::get_ipc_stats)
+                .service(output::add_output)
+                .service(output::update_output)
+                .service(output::delete_output)
+                .service(output::get_output_list)
+                .service(record::get_recording_list)
+                .service(record::download_recordings)
+                .service(live::get_live)
                 .service(account::change_password)
             // .service(fs::Files::new("/admin", "./public").index_file("default.html"))
         })
         .bind(bind)?
         .run()
-        .await
+        .await?;
+
+        // 停止所有推流，下次启动时恢复
+        info!("Shutting down");
+        match shutdown_addr
+            .send(my_actor::Shutdown(shutdown_timeout))
+            .await
+        {
+            Ok(count) => info!("{} publishers stopped", count),
+            Err(e) => error!("{}", e),
+        }
+        Ok(())
     }
 }
@@ -52,18 +52,45 @@ impl Result<()> {
         message: "Old password error",
     };
 
+    pub const VERSION_CONFLICT: Error = Error {
+        code: 10007,
+        message: "Modified by another request",
+    };
+
+    // 已经开始推流，但连接断开正在重连
+    pub const RECONNECTING: Error = Error {
+        code: 10008,
+        message: "Started, reconnecting",
+    };
+
     // 50000 程序错误相关
     pub const SESSION_SET_ERROR: Error = Error {
         code: 50001,
         message: "Session set error",
     };
 
+    pub const SNAPSHOT_ERROR: Error = Error {
+        code: 50002,
+        message: "Snapshot error",
+    };
+
+    pub const PUSH_ERROR: Error = Error {
+        code: 50003,
+        message: "Push error",
+    };
+
     // 60000 数据库错误相关
     pub const DB_OPERATION_ERROR: Error = Error {
         code: 60001,
         message: "Database operational error",
     };
 
+    // 70000 文件错误相关
+    pub const FILE_OPERATION_ERROR: Error = Error {
+        code: 70001,
+        message: "File operational error",
+    };
+
     pub fn success() -> Self {
         let e = Result::SUCCESS;
         Result {
@@ -111,7 +138,7 @@ pub struct Page<T> {
 impl<T> Page<T> {
     pub fn new(total: u64, rows: T) -> Self {
         Page {
-            total: total,
+            total,
             rows: Some(rows),
         }
     }
@@ -0,0 +1,178 @@
+use actix::prelude::*;
+use futures::future::{FutureExt, LocalBoxFuture};
+use serde::Serialize;
+use std::panic::{self, AssertUnwindSafe};
+use std::time::{Duration, Instant};
+
+use crate::util::time;
+
+use log::{error, info};
+
+/// 任务执行成功时返回一句说明，失败时返回错误原因
+pub type JobResult = std::result::Result<String, String>;
+
+/// 异步任务，在 Scheduler 中执行，等待期间不影响其它任务
+pub type JobFuture = LocalBoxFuture<'static, JobResult>;
+
+/// 定时任务最近一次的执行情况
+#[derive(Serialize, Debug, Clone)]
+pub struct JobStatus {
+    pub name: String,
+    pub interval: u64, // 单位毫秒，为 0 时只执行一次
+    pub run_count: u64,
+    pub error_count: u64,
+    pub last_run_time: Option<i64>,
+    pub last_duration: Option<u64>, // 单位毫秒
+    pub last_message: Option<String>,
+    pub last_error: Option<String>,
+}
+
+enum Run {
+    Sync(Box<dyn Fn() -> JobResult + Send>),
+    Async(Box<dyn Fn() -> JobFuture + Send>),
+}
+
+struct Job {
+    status: JobStatus,
+    delay: u64,
+    run: Run,
+    running: bool, // 异步任务正在执行
+}
+
+/// 执行定时任务的 actor，需要运行在独立的 Arbiter 上，
+/// 任务中阻塞的数据库和文件操作不会影响 HTTP 接口
+pub struct Scheduler {
+    jobs: Vec<Job>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Scheduler { jobs: vec![] }
+    }
+
+    /// 添加任务，启动 `delay` 毫秒后第一次执行，之后每 `interval` 毫秒执行一次，
+    /// `interval` 为 0 时只执行一次
+    pub fn job<F>(self, name: &str, delay: u64, interval: u64, run: F) -> Self
+    where
+        F: Fn() -> JobResult + Send + 'static,
+    {
+        self.add(name, delay, interval, Run::Sync(Box::new(run)))
+    }
+
+    /// 添加异步任务，上一次执行还没有结束时跳过本次执行
+    pub fn job_async<F>(self, name: &str, delay: u64, interval: u64, run: F) -> Self
+    where
+        F: Fn() -> JobFuture + Send + 'static,
+    {
+        self.add(name, delay, interval, Run::Async(Box::new(run)))
+    }
+
+    fn add(mut self, name: &str, delay: u64, interval: u64, run: Run) -> Self {
+        self.jobs.push(Job {
+            status: JobStatus {
+                name: name.to_string(),
+                interval,
+                run_count: 0,
+                error_count: 0,
+                last_run_time: None,
+                last_duration: None,
+                last_message: None,
+                last_error: None,
+            },
+            delay,
+            run,
+            running: false,
+        });
+        self
+    }
+
+    /// 在独立线程中启动
+    pub fn start_in_thread(self) -> Addr<Self> {
+        Scheduler::start_in_arbiter(&Arbiter::new(), move |_| self)
+    }
+
+    fn run_job(&mut self, index: usize, ctx: &mut Context<Self>) {
+        if self.jobs[index].running {
+            return;
+        }
+        let start = Instant::now();
+        // 任务中的 panic 只影响本次执行
+        let result = match &self.jobs[index].run {
+            Run::Sync(run) => panic::catch_unwind(AssertUnwindSafe(run)),
+            Run::Async(run) => match panic::catch_unwind(AssertUnwindSafe(run)) {
+                Err(e) => Err(e),
+                Ok(future) => {
+                    self.jobs[index].running = true;
+                    let future = AssertUnwindSafe(future).catch_unwind();
+                    ctx.spawn(future.into_actor(self).map(move |result, act, _| {
+                        act.jobs[index].running = false;
+                        act.finish_job(index, start, result);
+                    }));
+                    return;
+                }
+            },
+        };
+        self.finish_job(index, start, result);
+    }
+
+    /// 记录任务的执行结果
+    fn finish_job(&mut self, index: usize, start: Instant, result: std::thread::Result<JobResult>) {
+        let result = result.unwrap_or_else(|_| Err("job panicked".to_string()));
+        let status = &mut self.jobs[index].status;
+        status.run_count += 1;
+        status.last_run_time = Some(time::current_timestamp() as i64);
+        status.last_duration = Some(start.elapsed().as_millis() as u64);
+        match result {
+            Ok(message) => {
+                info!("Job {}: {}", status.name, message);
+                status.last_message = Some(message);
+                status.last_error = None;
+            }
+            Err(e) => {
+                error!("Job {} failed: {}", status.name, e);
+                status.error_count += 1;
+                status.last_message = None;
+                status.last_error = Some(e);
+            }
+        }
+    }
+}
+
+impl Actor for Scheduler {
+    type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        for (index, job) in self.jobs.iter().enumerate() {
+            let interval = job.status.interval;
+            ctx.run_later(Duration::from_millis(job.delay), move |act, ctx| {
+                act.run_job(index, ctx);
+                if interval > 0 {
+                    ctx.run_interval(Duration::from_millis(interval), move |act, ctx| {
+                        act.run_job(index, ctx)
+                    });
+                }
+            });
+        }
+    }
+}
+
+/// 查询所有定时任务的执行情况
+pub struct GetJobs;
+
+impl Message for GetJobs {
+    type Result = Vec<JobStatus>;
+}
+
+impl Handler<GetJobs> for Scheduler {
+    type Result = MessageResult<GetJobs>;
+
+    fn handle(&mut self, _: GetJobs, _: &mut Self::Context) -> Self::Result {
+        MessageResult(self.jobs.iter().map(|job| job.status.clone()).collect())
+    }
+}
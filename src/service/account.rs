@@ -32,9 +32,8 @@ impl Account {
 }
 
 use crate::db;
-use rusqlite::{params, Result};
+use rusqlite::{params, Result, Row};
 
-const CREATE_TABLE_SQL: &str = "CREATE TABLE IF NOT EXISTS tb_account (uid INTEGER NOT NULL,username VARCHAR(15) NOT NULL UNIQUE,password VARCHAR(32) NOT NULL,token VARCHAR(32) NOT NULL UNIQUE,create_time BIGINT NOT NULL,update_time BIGINT NULL,PRIMARY KEY (uid))";
 const HAVE_DATA_SQL: &str = "SELECT 1 FROM tb_account LIMIT 1";
 const INSERT_SQL: &str =
     "INSERT INTO tb_account(username, password, token, create_time) VALUES(?,?,?,?)";
@@ -45,18 +44,30 @@ const GET_BY_ID_SQL: &str = "SELECT * FROM tb_account WHERE uid=?";
 const GET_BY_USERNAME_SQL: &str = "SELECT * FROM tb_account WHERE username=?";
 const GET_BY_TOKEN_SQL: &str = "SELECT * FROM tb_account WHERE token=?";
 
+fn from_row(row: &Row) -> Result<Account> {
+    Ok(Account::new(
+        row.get("uid")?,
+        row.get("username")?,
+        row.get("password")?,
+        row.get("token")?,
+        row.get("create_time")?,
+        row.get("update_time")?,
+    ))
+}
+
 #[derive(Clone)]
-pub struct AccountService {}
+pub struct AccountService {
+    pool: db::Pool,
+}
 
 impl AccountService {
-    pub fn new() -> Result<Self> {
-        db::create_table(CREATE_TABLE_SQL)?;
-        Ok(AccountService {})
+    pub fn new(pool: db::Pool) -> Self {
+        AccountService { pool }
     }
 
     /// 数据库初始化方法，包括初始化默认登录的用户信息
     pub fn init_data(&self) -> Result<usize, rusqlite::Error> {
-        let conn = db::conn()?;
+        let conn = self.pool.conn()?;
         let mut stmt = conn.prepare(HAVE_DATA_SQL)?;
         match stmt.exists(params![]) {
             Err(e) => Err(e),
@@ -83,7 +94,7 @@ impl AccountService {
 
     /// 执行 Insert SQL添加一条Account数据到数据库中
     pub fn insert(&self, account: Account) -> Result<usize> {
-        db::conn()?.execute(
+        self.pool.conn()?.execute(
             INSERT_SQL,
             params![
                 account.username,
@@ -96,7 +107,7 @@ impl AccountService {
 
     /// 执行Update SQL修改数据库中的Account数据
     pub fn update(&self, account: Account) -> Result<usize> {
-        db::conn()?.execute(
+        self.pool.conn()?.execute(
             UPDATE_SQL,
             params![
                 account.username,
@@ -110,7 +121,7 @@ impl AccountService {
 
     /// 执行Update SQL修改数据库中的Account的密码
     pub fn change_password(&self, password: String, uid: i32) -> Result<usize> {
-        db::conn()?.execute(
+        self.pool.conn()?.execute(
             CHANGE_PASSWORD_SQL,
             params![password, util::time::current_timestamp() as i64, uid],
         )
@@ -118,18 +129,9 @@ impl AccountService {
 
     /// 根据uid来获取一个Account信息
     pub fn get(&self, uid: i32) -> Result<Option<Account>> {
-        let conn = db::conn()?;
+        let conn = self.pool.conn()?;
         let mut stmt = conn.prepare(GET_BY_ID_SQL)?;
-        let mut rows = stmt.query_map(params![uid], |row| {
-            Ok(Account::new(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                row.get(4)?,
-                row.get(5)?,
-            ))
-        })?;
+        let mut rows = stmt.query_map(params![uid], from_row)?;
         let row = match rows.next() {
             None => None,
             Some(row) => Some(row?),
@@ -139,18 +141,9 @@ impl AccountService {
 
     /// 根据username来获取一个Account信息
     pub fn get_by_username(&self, username: String) -> Result<Option<Account>> {
-        let conn = db::conn()?;
+        let conn = self.pool.conn()?;
         let mut stmt = conn.prepare(GET_BY_USERNAME_SQL)?;
-        let mut rows = stmt.query_map(params![username], |row| {
-            Ok(Account::new(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                row.get(4)?,
-                row.get(5)?,
-            ))
-        })?;
+        let mut rows = stmt.query_map(params![username], from_row)?;
         let row = match rows.next() {
             None => None,
             Some(row) => Some(row?),
@@ -160,18 +153,9 @@ impl AccountService {
 
     /// 根据token来获取一个Account信息
     pub fn get_by_token(&self, token: String) -> Result<Option<Account>> {
-        let conn = db::conn()?;
+        let conn = self.pool.conn()?;
         let mut stmt = conn.prepare(GET_BY_TOKEN_SQL)?;
-        let mut rows = stmt.query_map(params![token], |row| {
-            Ok(Account::new(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                row.get(4)?,
-                row.get(5)?,
-            ))
-        })?;
+        let mut rows = stmt.query_map(params![token], from_row)?;
         let row = match rows.next() {
             None => None,
             Some(row) => Some(row?),
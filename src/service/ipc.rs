@@ -1,4 +1,66 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// 推流状态，只由 `MyActor` 修改
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IpcState {
+    Stopped,      // 保留状态，摄像头输入结束时按断开重连，当前不会进入该状态
+    Starting,     // 正在打开输入和推流地址
+    Streaming,    // 正在推流
+    Reconnecting, // 断开后等待重连
+    Failed,       // 重连次数用完，手动启动前不再重连
+    Disabled,     // 未启动或者已手动停止
+    Shutdown,     // 程序退出时停止，下次启动时恢复推流
+}
+
+impl IpcState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IpcState::Stopped => "stopped",
+            IpcState::Starting => "starting",
+            IpcState::Streaming => "streaming",
+            IpcState::Reconnecting => "reconnecting",
+            IpcState::Failed => "failed",
+            IpcState::Disabled => "disabled",
+            IpcState::Shutdown => "shutdown",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "stopped" => Some(IpcState::Stopped),
+            "starting" => Some(IpcState::Starting),
+            "streaming" => Some(IpcState::Streaming),
+            "reconnecting" => Some(IpcState::Reconnecting),
+            "failed" => Some(IpcState::Failed),
+            "disabled" => Some(IpcState::Disabled),
+            "shutdown" => Some(IpcState::Shutdown),
+            _ => None,
+        }
+    }
+
+    /// 推流线程正在运行
+    pub fn is_active(&self) -> bool {
+        matches!(
+            self,
+            IpcState::Starting | IpcState::Streaming | IpcState::Reconnecting
+        )
+    }
+}
+
+impl ToSql for IpcState {
+    fn to_sql(&self) -> Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for IpcState {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let name = value.as_str()?;
+        IpcState::from_name(name).ok_or_else(|| FromSqlError::Other(name.into()))
+    }
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Ipc {
@@ -7,122 +69,379 @@ pub struct Ipc {
     pub name: String,
     pub rtsp: String,
     pub rtmp: String,
-    pub enable: i32, // 0 禁用推流  1 启用推流
-    pub reason: Option<String>,
-    pub retry_count: i32,
+    pub state: IpcState,
+    pub reason: Option<String>, // 最近一次失败的原因
+    pub retry_count: i32,       // 重连的次数，手动启动时清零
     pub create_time: i64,
     pub update_time: Option<i64>,
+    pub output_format: String, // flv rtsp srt udp
+    pub transport: String,     // tcp udp udp_multicast http
+    pub input_options: BTreeMap<String, String>,
+    pub output_options: BTreeMap<String, String>,
+    pub video_codec: String,  // copy 直接转封装  h264 转码为H.264
+    pub video_bitrate: i32,   // 转码码率，单位kbps
+    pub video_gop: i32,       // 关键帧间隔，0 为2秒
+    pub video_preset: String, // 编码速度，如 ultrafast veryfast medium
+    pub video_width: i32,     // 0 保持原始分辨率
+    pub video_height: i32,
+    pub audio_codec: String, // copy 直接转封装  aac 转码为AAC  drop 丢弃音频
+    pub record: i32,         // 0 不录像  1 推流时同时录像
+    pub record_format: String, // ts mp4
+    pub record_segment: i32, // 录像分段时长，单位秒
+    pub live: i32,           // 0 不提供本地直播  1 推流时提供HLS直播
+    pub state_time: Option<i64>, // 最近一次状态变化的时间
+    // 重连参数，为空时使用配置文件中的设置
+    pub retry_max_attempts: Option<i32>, // 重连的最大次数
+    pub retry_delay: Option<i64>,        // 初始等待时间，单位毫秒
+    pub retry_max_delay: Option<i64>,    // 最长等待时间，单位毫秒
+    pub retry_window: Option<i64>,       // 统计重连次数的时间窗口，单位秒
+    pub tag: Option<String>,             // 分组标签，用于列表筛选
+    pub version: i32,                    // 配置的版本号，每次修改配置时加 1，推流状态变化时不变
 }
 
-impl Ipc {
-    pub fn new(
-        id: i32,
-        key: String,
-        name: String,
-        rtsp: String,
-        rtmp: String,
-        enable: i32,
-        reason: Option<String>,
-        retry_count: i32,
-        create_time: i64,
-        update_time: Option<i64>,
-    ) -> Self {
+impl Default for Ipc {
+    fn default() -> Self {
         Ipc {
-            id,
-            key,
-            name,
-            rtsp,
-            rtmp,
-            enable,
-            reason,
-            retry_count,
-            create_time,
-            update_time,
+            id: 0,
+            key: String::new(),
+            name: String::new(),
+            rtsp: String::new(),
+            rtmp: String::new(),
+            state: IpcState::Disabled,
+            reason: None,
+            retry_count: 0,
+            create_time: 0,
+            update_time: None,
+            output_format: "flv".to_string(),
+            transport: "tcp".to_string(),
+            input_options: BTreeMap::new(),
+            output_options: BTreeMap::new(),
+            video_codec: "copy".to_string(),
+            video_bitrate: 0,
+            video_gop: 0,
+            video_preset: "veryfast".to_string(),
+            video_width: 0,
+            video_height: 0,
+            audio_codec: "copy".to_string(),
+            record: 0,
+            record_format: "ts".to_string(),
+            record_segment: 60,
+            live: 0,
+            state_time: None,
+            retry_max_attempts: None,
+            retry_delay: None,
+            retry_max_delay: None,
+            retry_window: None,
+            tag: None,
+            version: 0,
+        }
+    }
+}
+
+/// Ipc列表的排序字段
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IpcSort {
+    #[default]
+    Id,
+    Key,
+    Name,
+    State,
+    CreateTime,
+    UpdateTime,
+    StateTime,
+}
+
+impl IpcSort {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "id" => Some(IpcSort::Id),
+            "key" => Some(IpcSort::Key),
+            "name" => Some(IpcSort::Name),
+            "state" => Some(IpcSort::State),
+            "create_time" => Some(IpcSort::CreateTime),
+            "update_time" => Some(IpcSort::UpdateTime),
+            "state_time" => Some(IpcSort::StateTime),
+            _ => None,
         }
     }
+
+    /// 排序字段只能从固定的列名中选择，不能拼接请求参数
+    fn column(&self) -> &'static str {
+        match self {
+            IpcSort::Id => "id",
+            IpcSort::Key => "key",
+            IpcSort::Name => "name",
+            IpcSort::State => "state",
+            IpcSort::CreateTime => "create_time",
+            IpcSort::UpdateTime => "update_time",
+            IpcSort::StateTime => "state_time",
+        }
+    }
+}
+
+/// Ipc列表的查询条件，为空的条件不参与筛选
+#[derive(Debug, Default, Clone)]
+pub struct IpcQuery {
+    pub keyword: Option<String>, // 模糊匹配 key name rtsp rtmp
+    pub state: Option<IpcState>,
+    pub tag: Option<String>,
+    pub has_error: Option<bool>, // 是否有失败原因
+    pub start_time: Option<i64>, // 创建时间范围，包含两端
+    pub end_time: Option<i64>,
+    pub sort: IpcSort,
+    pub desc: bool,
+}
+
+impl IpcQuery {
+    /// 生成查询条件，所有参数都通过占位符传入
+    fn condition(&self) -> (String, Vec<Box<dyn ToSql>>) {
+        let mut sql = String::new();
+        let mut values: Vec<Box<dyn ToSql>> = Vec::new();
+        if let Some(keyword) = &self.keyword {
+            // 通配符按普通字符匹配
+            let pattern = format!(
+                "%{}%",
+                keyword
+                    .replace('\\', "\\\\")
+                    .replace('%', "\\%")
+                    .replace('_', "\\_")
+            );
+            sql += " AND (key LIKE ? ESCAPE '\\' OR name LIKE ? ESCAPE '\\' OR rtsp LIKE ? ESCAPE '\\' OR rtmp LIKE ? ESCAPE '\\')";
+            for _ in 0..4 {
+                values.push(Box::new(pattern.clone()));
+            }
+        }
+        if let Some(state) = self.state {
+            sql += " AND state = ?";
+            values.push(Box::new(state));
+        }
+        if let Some(tag) = &self.tag {
+            sql += " AND tag = ?";
+            values.push(Box::new(tag.clone()));
+        }
+        match self.has_error {
+            Some(true) => sql += " AND reason IS NOT NULL AND reason != ''",
+            Some(false) => sql += " AND (reason IS NULL OR reason = '')",
+            None => {}
+        }
+        if let Some(start_time) = self.start_time {
+            sql += " AND create_time >= ?";
+            values.push(Box::new(start_time));
+        }
+        if let Some(end_time) = self.end_time {
+            sql += " AND create_time <= ?";
+            values.push(Box::new(end_time));
+        }
+        (sql, values)
+    }
 }
 
 use crate::db;
-use rusqlite::{params, Result};
+use crate::util;
+use rusqlite::types::{FromSql, FromSqlError, FromSqlResult, ToSql, ToSqlOutput, Type, ValueRef};
+use rusqlite::{params, Error, Result, Row};
 
-const CREATE_TABLE_SQL: &str = "CREATE TABLE IF NOT EXISTS tb_ipc (id INTEGER NOT NULL,key VARCHAR(32) NOT NULL UNIQUE,name VARCHAR(50) NOT NULL,rtsp VARCHAR(255) NOT NULL,rtmp VARCHAR(255) NOT NULL,enable TINYINT NOT NULL DEFAULT 0,reason VARCHAR(255) NULL,retry_count INTEGER NOT NULL DEFAULT 0,create_time BIGINT NOT NULL,update_time BIGINT NULL,PRIMARY KEY (id))";
 const INSERT_SQL: &str =
-    "INSERT INTO tb_ipc(key, name, rtsp, rtmp, enable, create_time) VALUES(?,?,?,?,?,?)";
+    "INSERT INTO tb_ipc(key, name, rtsp, rtmp, create_time, output_format, transport, input_options, output_options, video_codec, video_bitrate, video_gop, video_preset, video_width, video_height, audio_codec, record, record_format, record_segment, live, retry_max_attempts, retry_delay, retry_max_delay, retry_window, tag) VALUES(?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?,?)";
 const UPDATE_SQL: &str =
-    "UPDATE tb_ipc SET key=?, name=?, rtsp=?, rtmp=?, enable=?, reason=?, retry_count=?, update_time=? WHERE id=?";
+    "UPDATE tb_ipc SET key=?, name=?, rtsp=?, rtmp=?, update_time=?, output_format=?, transport=?, input_options=?, output_options=?, video_codec=?, video_bitrate=?, video_gop=?, video_preset=?, video_width=?, video_height=?, audio_codec=?, record=?, record_format=?, record_segment=?, live=?, retry_max_attempts=?, retry_delay=?, retry_max_delay=?, retry_window=?, tag=?, version=version+1 WHERE id=? AND version=?";
+const UPDATE_REASON_SQL: &str = "UPDATE tb_ipc SET reason=? WHERE id=?";
+const UPDATE_STATE_SQL: &str = "UPDATE tb_ipc SET state=?, reason=?, state_time=? WHERE id=?";
+const UPDATE_RETRY_COUNT_SQL: &str = "UPDATE tb_ipc SET retry_count=? WHERE id=?";
 const DELETE_SQL: &str = "DELETE FROM tb_ipc WHERE id=?";
+const DELETE_OUTPUTS_SQL: &str = "DELETE FROM tb_ipc_output WHERE ipc_id=?";
 const GET_BY_ID_SQL: &str = "SELECT * FROM tb_ipc WHERE id=?";
 const GET_BY_KEY_SQL: &str = "SELECT * FROM tb_ipc WHERE key=?";
 const GET_LIST_SQL: &str = "SELECT * FROM tb_ipc WHERE 1=1";
+const GET_STATES_SQL: &str = "SELECT id, state FROM tb_ipc WHERE id IN";
 const COUNT_SQL: &str = "SELECT COUNT(1) FROM tb_ipc WHERE 1=1";
 
-const MAX_ROWS: i32 = 64;
+/// 推流线程正在运行的状态
+const ACTIVE_CONDITION: &str = " AND state IN ('starting', 'streaming', 'reconnecting')";
+
+/// 程序启动时需要恢复推流的状态，包括非正常结束时仍在推流和退出时停止的Ipc
+const RESUME_CONDITION: &str =
+    " AND state IN ('starting', 'streaming', 'reconnecting', 'shutdown')";
+
+/// 内部遍历Ipc时每次读取的行数
+const SCAN_ROWS: u32 = 500;
+
+/// 读取以JSON格式保存的FFmpeg参数
+pub(super) fn options_from_row(row: &Row, column: &str) -> Result<BTreeMap<String, String>> {
+    match row.get::<_, Option<String>>(column)? {
+        None => Ok(BTreeMap::new()),
+        Some(json) => serde_json::from_str(&json).map_err(|e| {
+            let idx = row.column_index(column).unwrap_or_default();
+            Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e))
+        }),
+    }
+}
+
+/// 将FFmpeg参数转换为JSON格式保存，没有参数时保存为NULL
+pub(super) fn options_to_sql(options: &BTreeMap<String, String>) -> Option<String> {
+    if options.is_empty() {
+        None
+    } else {
+        serde_json::to_string(options).ok()
+    }
+}
+
+/// 将查询结果中的一行转换为Ipc
+fn from_row(row: &Row) -> Result<Ipc> {
+    Ok(Ipc {
+        id: row.get("id")?,
+        key: row.get("key")?,
+        name: row.get("name")?,
+        rtsp: row.get("rtsp")?,
+        rtmp: row.get("rtmp")?,
+        state: row.get("state")?,
+        reason: row.get("reason")?,
+        retry_count: row.get("retry_count")?,
+        create_time: row.get("create_time")?,
+        update_time: row.get("update_time")?,
+        output_format: row.get("output_format")?,
+        transport: row.get("transport")?,
+        input_options: options_from_row(row, "input_options")?,
+        output_options: options_from_row(row, "output_options")?,
+        video_codec: row.get("video_codec")?,
+        video_bitrate: row.get("video_bitrate")?,
+        video_gop: row.get("video_gop")?,
+        video_preset: row.get("video_preset")?,
+        video_width: row.get("video_width")?,
+        video_height: row.get("video_height")?,
+        audio_codec: row.get("audio_codec")?,
+        record: row.get("record")?,
+        record_format: row.get("record_format")?,
+        record_segment: row.get("record_segment")?,
+        live: row.get("live")?,
+        state_time: row.get("state_time")?,
+        retry_max_attempts: row.get("retry_max_attempts")?,
+        retry_delay: row.get("retry_delay")?,
+        retry_max_delay: row.get("retry_max_delay")?,
+        retry_window: row.get("retry_window")?,
+        tag: row.get("tag")?,
+        version: row.get("version")?,
+    })
+}
 
 #[derive(Clone)]
-pub struct IpcService;
+pub struct IpcService {
+    pool: db::Pool,
+}
 
 impl IpcService {
-    pub fn new() -> Result<Self> {
-        db::create_table(CREATE_TABLE_SQL)?;
-        Ok(IpcService {})
+    pub fn new(pool: db::Pool) -> Self {
+        IpcService { pool }
     }
 
     /// 执行Insert SQL往数据库中添加一条Ipc数据
     pub fn insert(&self, ipc: Ipc) -> Result<usize> {
-        db::conn()?.execute(
+        self.pool.conn()?.execute(
             INSERT_SQL,
             params![
                 ipc.key,
                 ipc.name,
                 ipc.rtsp,
                 ipc.rtmp,
-                ipc.enable,
-                ipc.create_time
+                ipc.create_time,
+                ipc.output_format,
+                ipc.transport,
+                options_to_sql(&ipc.input_options),
+                options_to_sql(&ipc.output_options),
+                ipc.video_codec,
+                ipc.video_bitrate,
+                ipc.video_gop,
+                ipc.video_preset,
+                ipc.video_width,
+                ipc.video_height,
+                ipc.audio_codec,
+                ipc.record,
+                ipc.record_format,
+                ipc.record_segment,
+                ipc.live,
+                ipc.retry_max_attempts,
+                ipc.retry_delay,
+                ipc.retry_max_delay,
+                ipc.retry_window,
+                ipc.tag
             ],
         )
     }
 
-    /// 执行Update SQL修改数据库中的Ipc数据
-    pub fn update(&self, ipc: Ipc) -> Result<usize> {
-        db::conn()?.execute(
+    /// 修改Ipc配置，不修改推流状态
+    /// 只在版本号与 `ipc.version` 一致时修改，返回 0 时说明已被其它请求修改或者删除
+    pub fn update_config(&self, ipc: Ipc) -> Result<usize> {
+        self.pool.conn()?.execute(
             UPDATE_SQL,
             params![
                 ipc.key,
                 ipc.name,
                 ipc.rtsp,
                 ipc.rtmp,
-                ipc.enable,
-                ipc.reason,
-                ipc.retry_count,
                 ipc.update_time,
-                ipc.id
+                ipc.output_format,
+                ipc.transport,
+                options_to_sql(&ipc.input_options),
+                options_to_sql(&ipc.output_options),
+                ipc.video_codec,
+                ipc.video_bitrate,
+                ipc.video_gop,
+                ipc.video_preset,
+                ipc.video_width,
+                ipc.video_height,
+                ipc.audio_codec,
+                ipc.record,
+                ipc.record_format,
+                ipc.record_segment,
+                ipc.live,
+                ipc.retry_max_attempts,
+                ipc.retry_delay,
+                ipc.retry_max_delay,
+                ipc.retry_window,
+                ipc.tag,
+                ipc.id,
+                ipc.version
             ],
         )
     }
 
-    /// 执行Delete SQL从数据库中删除一条Ipc数据
-    pub fn delete(&self, id: i32) -> Result<usize> {
-        db::conn()?.execute(DELETE_SQL, params![id])
+    /// 推流过程中只修改异常原因，避免覆盖其它字段
+    pub fn update_reason(&self, id: i32, reason: Option<String>) -> Result<usize> {
+        self.pool
+            .conn()?
+            .execute(UPDATE_REASON_SQL, params![reason, id])
+    }
+
+    /// 修改推流状态，同时记录状态变化的时间
+    pub fn update_state(&self, id: i32, state: IpcState, reason: Option<String>) -> Result<usize> {
+        let state_time = util::time::current_timestamp() as i64;
+        self.pool
+            .conn()?
+            .execute(UPDATE_STATE_SQL, params![state, reason, state_time, id])
+    }
+
+    /// 修改重连的次数
+    pub fn update_retry_count(&self, id: i32, retry_count: i32) -> Result<usize> {
+        self.pool
+            .conn()?
+            .execute(UPDATE_RETRY_COUNT_SQL, params![retry_count, id])
+    }
+
+    /// 在同一个事务中删除Ipc及其附加的推流地址
+    pub fn delete_with_outputs(&self, id: i32) -> Result<usize> {
+        let mut conn = self.pool.conn()?;
+        let tx = conn.transaction()?;
+        tx.execute(DELETE_OUTPUTS_SQL, params![id])?;
+        let deleted = tx.execute(DELETE_SQL, params![id])?;
+        tx.commit()?;
+        Ok(deleted)
     }
 
     /// 通过id来获取一条Ipc数据
     pub fn get(&self, id: i32) -> Result<Option<Ipc>> {
-        let conn = db::conn()?;
+        let conn = self.pool.conn()?;
         let mut stmt = conn.prepare(GET_BY_ID_SQL)?;
-        let mut rows = stmt.query_map(params![id], |row| {
-            Ok(Ipc::new(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                row.get(4)?,
-                row.get(5)?,
-                row.get(6)?,
-                row.get(7)?,
-                row.get(8)?,
-                row.get(9)?,
-            ))
-        })?;
+        let mut rows = stmt.query_map(params![id], from_row)?;
         let row = match rows.next() {
             None => None,
             Some(row) => Some(row?),
@@ -130,24 +449,29 @@ impl IpcService {
         Ok(row)
     }
 
+    /// 按id批量获取Ipc的推流状态，已删除的Ipc不在返回结果中
+    pub fn get_states(&self, ids: &[i32]) -> Result<HashMap<i32, IpcState>> {
+        let conn = self.pool.conn()?;
+        let mut states = HashMap::new();
+        for chunk in ids.chunks(SCAN_ROWS as usize) {
+            let placeholders = vec!["?"; chunk.len()].join(",");
+            let sql = format!("{} ({})", GET_STATES_SQL, placeholders);
+            let mut stmp = conn.prepare(&sql)?;
+            let values: Vec<&dyn ToSql> = chunk.iter().map(|id| id as &dyn ToSql).collect();
+            let rows = stmp.query_map(&values, |row| Ok((row.get(0)?, row.get(1)?)))?;
+            for row in rows {
+                let (id, state) = row?;
+                states.insert(id, state);
+            }
+        }
+        Ok(states)
+    }
+
     /// 通过key来获取一条Ipc数据
     pub fn get_by_key(&self, key: String) -> Result<Option<Ipc>> {
-        let conn = db::conn()?;
+        let conn = self.pool.conn()?;
         let mut stmt = conn.prepare(GET_BY_KEY_SQL)?;
-        let mut rows = stmt.query_map(params![key], |row| {
-            Ok(Ipc::new(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                row.get(4)?,
-                row.get(5)?,
-                row.get(6)?,
-                row.get(7)?,
-                row.get(8)?,
-                row.get(9)?,
-            ))
-        })?;
+        let mut rows = stmt.query_map(params![key], from_row)?;
         let row = match rows.next() {
             None => None,
             Some(row) => Some(row?),
@@ -155,94 +479,70 @@ impl IpcService {
         Ok(row)
     }
 
-    /// 获取Ipc列表
-    pub fn get_list(&self, page: u32, rows: u32, keyword: Option<String>) -> Result<Vec<Ipc>> {
-        let conn = db::conn()?;
-        let mut sql = String::from(GET_LIST_SQL);
-        if keyword.is_some() {
-            let keyword = keyword.unwrap();
-            sql += &format!(
-                " AND key LIKE '%{0}%' OR name LIKE '%{0}%' OR rtsp LIKE '%{0}%' OR rtmp LIKE '%{0}%'",
-                keyword
-            );
-        }
-        sql += " LIMIT ? OFFSET ?";
+    /// 按条件分页查询Ipc列表，返回符合条件的总数和当前页的数据，`page` 从 1 开始
+    pub fn search(&self, query: &IpcQuery, page: u32, rows: u32) -> Result<(u64, Vec<Ipc>)> {
+        let conn = self.pool.conn()?;
+        let (condition, values) = query.condition();
+        let mut values: Vec<&dyn ToSql> = values.iter().map(|value| value.as_ref()).collect();
+        let count_sql = String::from(COUNT_SQL) + &condition;
+        let total: i64 = conn.query_row(&count_sql, &values, |row| row.get(0))?;
+        // 排序字段相同时按id排序，保证分页稳定
+        let order = if query.desc { "DESC" } else { "ASC" };
+        let sql = format!(
+            "{}{} ORDER BY {} {}, id {} LIMIT ? OFFSET ?",
+            GET_LIST_SQL,
+            condition,
+            query.sort.column(),
+            order,
+            order
+        );
+        let limit = rows as i64;
+        let offset = (page.max(1) as i64 - 1) * limit;
+        values.push(&limit);
+        values.push(&offset);
         let mut stmp = conn.prepare(&sql)?;
-        let offset = (page - 1) * rows;
-        let rows = stmp.query_map(params![rows, offset], |row| {
-            Ok(Ipc::new(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                row.get(4)?,
-                row.get(5)?,
-                row.get(6)?,
-                row.get(7)?,
-                row.get(8)?,
-                row.get(9)?,
-            ))
-        })?;
+        let rows = stmp.query_map(&values, from_row)?;
         let mut row_list: Vec<Ipc> = Vec::new();
         for row in rows {
             row_list.push(row?);
         }
-        Ok(row_list)
+        Ok((total as u64, row_list))
     }
 
-    /// 获取启用状态Ipc列表
-    pub fn get_enable_list(&self) -> Result<Vec<Ipc>> {
-        let conn = db::conn()?;
-        let mut sql = String::from(GET_LIST_SQL);
-        sql += " AND enable = 1";
-        sql += " LIMIT ? OFFSET ?";
-        let mut stmp = conn.prepare(&sql)?;
-        let rows = MAX_ROWS;
-        let offset = (1 - 1) * rows;
-        let rows = stmp.query_map(params![rows, offset], |row| {
-            Ok(Ipc::new(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                row.get(4)?,
-                row.get(5)?,
-                row.get(6)?,
-                row.get(7)?,
-                row.get(8)?,
-                row.get(9)?,
-            ))
-        })?;
+    /// 获取推流中Ipc列表，包括正在启动和等待重连的Ipc
+    pub fn get_active_list(&self) -> Result<Vec<Ipc>> {
         let mut row_list: Vec<Ipc> = Vec::new();
-        for row in rows {
-            row_list.push(row?);
+        let mut after_id = 0;
+        loop {
+            let page = self.get_active_page(after_id, SCAN_ROWS)?;
+            let done = page.len() < SCAN_ROWS as usize;
+            if let Some(ipc) = page.last() {
+                after_id = ipc.id;
+            }
+            row_list.extend(page);
+            if done {
+                return Ok(row_list);
+            }
         }
-        Ok(row_list)
     }
 
-    /// 获取异常状态Ipc列表
-    pub fn get_list_by_reason(&self, less_retry_count: u32) -> Result<Vec<Ipc>> {
-        let conn = db::conn()?;
+    /// 按id顺序分批获取推流中的Ipc，`after_id` 为上一批最后一条的id，第一批传 0
+    pub fn get_active_page(&self, after_id: i32, rows: u32) -> Result<Vec<Ipc>> {
+        self.get_page(ACTIVE_CONDITION, after_id, rows)
+    }
+
+    /// 按id顺序分批获取程序启动时需要恢复推流的Ipc
+    pub fn get_resume_page(&self, after_id: i32, rows: u32) -> Result<Vec<Ipc>> {
+        self.get_page(RESUME_CONDITION, after_id, rows)
+    }
+
+    fn get_page(&self, condition: &str, after_id: i32, rows: u32) -> Result<Vec<Ipc>> {
+        let conn = self.pool.conn()?;
         let mut sql = String::from(GET_LIST_SQL);
-        sql += " AND enable = 0 AND reason IS NOT NULL AND retry_count < ?";
-        sql += " LIMIT ? OFFSET ?";
+        sql += condition;
+        sql += " AND id > ? ORDER BY id LIMIT ?";
         let mut stmp = conn.prepare(&sql)?;
-        let rows = MAX_ROWS;
-        let offset = (1 - 1) * rows;
-        let rows = stmp.query_map(params![less_retry_count, rows, offset], |row| {
-            Ok(Ipc::new(
-                row.get(0)?,
-                row.get(1)?,
-                row.get(2)?,
-                row.get(3)?,
-                row.get(4)?,
-                row.get(5)?,
-                row.get(6)?,
-                row.get(7)?,
-                row.get(8)?,
-                row.get(9)?,
-            ))
-        })?;
+        let rows = stmp.query_map(params![after_id, rows], from_row)?;
         let mut row_list: Vec<Ipc> = Vec::new();
         for row in rows {
             row_list.push(row?);
@@ -252,7 +552,7 @@ impl IpcService {
 
     /// 统计IPC数量
     pub fn count(&self) -> Result<u64> {
-        let conn = db::conn()?;
+        let conn = self.pool.conn()?;
         let mut stmp = conn.prepare(COUNT_SQL)?;
         let count: i64 = stmp.query_row(params![], |row| {
             let count: i64 = row.get(0)?;
@@ -261,11 +561,11 @@ impl IpcService {
         Ok(count as u64)
     }
 
-    /// 获取启用状态Ipc的数量
-    pub fn count_enable(&self) -> Result<u64> {
-        let conn = db::conn()?;
+    /// 获取推流中Ipc的数量
+    pub fn count_active(&self) -> Result<u64> {
+        let conn = self.pool.conn()?;
         let mut sql = String::from(COUNT_SQL);
-        sql += " AND enable = 1";
+        sql += ACTIVE_CONDITION;
         let mut stmp = conn.prepare(&sql)?;
         let count: i64 = stmp.query_row(params![], |row| {
             let count: i64 = row.get(0)?;
@@ -274,11 +574,11 @@ impl IpcService {
         Ok(count as u64)
     }
 
-    /// 获取异常状态Ipc的数量
-    pub fn count_reason(&self) -> Result<u64> {
-        let conn = db::conn()?;
+    /// 获取失败状态Ipc的数量
+    pub fn count_failed(&self) -> Result<u64> {
+        let conn = self.pool.conn()?;
         let mut sql = String::from(COUNT_SQL);
-        sql += " AND enable = 0 AND reason IS NOT NULL";
+        sql += " AND state = 'failed'";
         let mut stmp = conn.prepare(&sql)?;
         let count: i64 = stmp.query_row(params![], |row| {
             let count: i64 = row.get(0)?;
@@ -287,3 +587,202 @@ impl IpcService {
         Ok(count as u64)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::{Path, PathBuf};
+
+    /// 每个测试使用单独的临时数据库
+    fn test_pool(name: &str) -> (PathBuf, db::Pool) {
+        let path = std::env::temp_dir().join(format!("dudu-{}-{}.db", name, std::process::id()));
+        remove_db(&path);
+        let pool = db::Pool::new(&crate::config::Database {
+            path: path.to_str().unwrap().to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        pool.migrate().unwrap();
+        (path, pool)
+    }
+
+    fn remove_db(path: &Path) {
+        for suffix in ["", "-wal", "-shm"].iter() {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
+
+    #[test]
+    fn active_list_scans_all_rows() {
+        let (path, pool) = test_pool("active");
+        let ipc_service = IpcService::new(pool.clone());
+
+        // 3000 路摄像头，其中每 3 路有 1 路在推流
+        let mut conn = pool.conn().unwrap();
+        let tx = conn.transaction().unwrap();
+        for i in 0..3000 {
+            let state = if i % 3 == 0 {
+                IpcState::Streaming
+            } else {
+                IpcState::Disabled
+            };
+            tx.execute(
+                "INSERT INTO tb_ipc(key, name, rtsp, rtmp, create_time, state) VALUES(?,?,?,?,?,?)",
+                params![
+                    format!("key{}", i),
+                    format!("ipc{}", i),
+                    "rtsp://127.0.0.1/live",
+                    "rtmp://127.0.0.1/live",
+                    0,
+                    state
+                ],
+            )
+            .unwrap();
+        }
+        tx.commit().unwrap();
+
+        let active_list = ipc_service.get_active_list().unwrap();
+        assert_eq!(active_list.len(), 1000);
+        assert!(active_list.windows(2).all(|w| w[0].id < w[1].id));
+        assert!(active_list
+            .iter()
+            .all(|ipc| ipc.state == IpcState::Streaming));
+        assert_eq!(ipc_service.count_active().unwrap(), 1000);
+
+        // 分批读取时不会重复或者遗漏
+        let mut after_id = 0;
+        let mut scanned = 0;
+        loop {
+            let page = ipc_service.get_active_page(after_id, 7).unwrap();
+            match page.last() {
+                None => break,
+                Some(ipc) => after_id = ipc.id,
+            }
+            scanned += page.len();
+        }
+        assert_eq!(scanned, 1000);
+
+        // 批量查询状态时超过单次查询数量也能全部返回，不存在的id被忽略
+        let ids: Vec<i32> = (1..=1200).chain(std::iter::once(5000)).collect();
+        let states = ipc_service.get_states(&ids).unwrap();
+        assert_eq!(states.len(), 1200);
+        assert_eq!(states[&1], IpcState::Streaming);
+        assert_eq!(states[&2], IpcState::Disabled);
+        assert!(!states.contains_key(&5000));
+
+        drop(conn);
+        drop(ipc_service);
+        drop(pool);
+        remove_db(&path);
+    }
+
+    #[test]
+    fn search_filters_and_counts() {
+        let (path, pool) = test_pool("search");
+        let ipc_service = IpcService::new(pool.clone());
+        for i in 0..30 {
+            let ipc = Ipc {
+                key: format!("key{}", i),
+                name: if i == 7 {
+                    "front_100%".to_string()
+                } else {
+                    format!("ipc{}", i)
+                },
+                rtsp: "rtsp://127.0.0.1/live".to_string(),
+                rtmp: "rtmp://127.0.0.1/live".to_string(),
+                create_time: i * 1000,
+                tag: Some(if i % 2 == 0 { "east" } else { "west" }.to_string()),
+                ..Default::default()
+            };
+            ipc_service.insert(ipc).unwrap();
+        }
+        for id in 1..=5 {
+            ipc_service
+                .update_state(id, IpcState::Failed, Some("timeout".to_string()))
+                .unwrap();
+        }
+
+        // 总数是筛选后的数量，不受分页影响
+        let query = IpcQuery {
+            tag: Some("east".to_string()),
+            ..Default::default()
+        };
+        let (total, list) = ipc_service.search(&query, 2, 10).unwrap();
+        assert_eq!(total, 15);
+        assert_eq!(list.len(), 5);
+
+        // 关键字中的条件不能绕过其它筛选条件
+        let query = IpcQuery {
+            keyword: Some("key' OR 1=1 --".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ipc_service.search(&query, 1, 100).unwrap().0, 0);
+        let query = IpcQuery {
+            keyword: Some("ipc1".to_string()),
+            state: Some(IpcState::Failed),
+            ..Default::default()
+        };
+        let (total, list) = ipc_service.search(&query, 1, 100).unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(list[0].key, "key1");
+
+        // 通配符按普通字符匹配
+        let query = IpcQuery {
+            keyword: Some("%".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(ipc_service.search(&query, 1, 100).unwrap().0, 1);
+
+        let query = IpcQuery {
+            has_error: Some(false),
+            start_time: Some(10000),
+            end_time: Some(19000),
+            sort: IpcSort::CreateTime,
+            desc: true,
+            ..Default::default()
+        };
+        let (total, list) = ipc_service.search(&query, 1, 100).unwrap();
+        assert_eq!(total, 10);
+        assert_eq!(list[0].create_time, 19000);
+        assert_eq!(list[9].create_time, 10000);
+
+        drop(ipc_service);
+        drop(pool);
+        remove_db(&path);
+    }
+
+    #[test]
+    fn update_config_checks_version() {
+        let (path, pool) = test_pool("version");
+        let ipc_service = IpcService::new(pool.clone());
+        ipc_service
+            .insert(Ipc {
+                key: "key0".to_string(),
+                ..Default::default()
+            })
+            .unwrap();
+        let ipc = ipc_service.get(1).unwrap().unwrap();
+        assert_eq!(ipc.version, 0);
+
+        // 推流状态变化不影响配置的版本号
+        ipc_service
+            .update_state(1, IpcState::Streaming, None)
+            .unwrap();
+        let mut first = ipc.clone();
+        first.name = "first".to_string();
+        assert_eq!(ipc_service.update_config(first).unwrap(), 1);
+
+        // 基于旧版本的修改不会覆盖
+        let mut second = ipc;
+        second.name = "second".to_string();
+        assert_eq!(ipc_service.update_config(second).unwrap(), 0);
+        let ipc = ipc_service.get(1).unwrap().unwrap();
+        assert_eq!(ipc.name, "first");
+        assert_eq!(ipc.version, 1);
+        assert_eq!(ipc.state, IpcState::Streaming);
+
+        drop(ipc_service);
+        drop(pool);
+        remove_db(&path);
+    }
+}
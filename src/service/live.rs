@@ -0,0 +1,47 @@
+use crate::config;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Clone)]
+pub struct LiveService {
+    path: PathBuf,
+    pub hls_time: u32,
+    pub hls_list_size: u32,
+}
+
+impl LiveService {
+    pub fn new(config: config::Live) -> Self {
+        LiveService {
+            path: PathBuf::from(config.path),
+            hls_time: config.hls_time,
+            hls_list_size: config.hls_list_size,
+        }
+    }
+
+    /// Ipc的HLS直播目录
+    pub fn dir(&self, ipc_id: i32) -> PathBuf {
+        self.path.join(ipc_id.to_string())
+    }
+
+    /// 删除Ipc的HLS直播目录，目录不存在时忽略
+    pub fn remove(&self, ipc_id: i32) -> io::Result<()> {
+        match fs::remove_dir_all(self.dir(ipc_id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// 读取HLS播放列表或分段，只允许读取直播目录中的 m3u8 和 ts 文件
+    pub fn read(&self, ipc_id: i32, name: &str) -> io::Result<Vec<u8>> {
+        let valid = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+            && !name.starts_with('.')
+            && (name.ends_with(".m3u8") || name.ends_with(".ts"));
+        if !valid {
+            return Err(io::Error::new(io::ErrorKind::NotFound, name.to_string()));
+        }
+        fs::read(self.dir(ipc_id).join(name))
+    }
+}
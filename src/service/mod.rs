@@ -1,25 +1,48 @@
 pub mod account;
 pub mod ipc;
+pub mod live;
+pub mod output;
+pub mod record;
 pub mod start;
 
+use crate::config::{self, Config};
+use crate::db;
+use actix_web::error::BlockingError;
+use actix_web::web;
 use log::info;
+use rusqlite::ffi;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct Service {
     pub ipc_service: ipc::IpcService,
     pub account_service: account::AccountService,
+    pub output_service: output::OutputService,
+    pub record_service: record::RecordService,
+    pub live_service: live::LiveService,
+    pub publisher_config: config::Publisher,
+}
+
+impl Default for Service {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Service {
     pub fn new() -> Self {
-        let ipc_service = match ipc::IpcService::new() {
+        let config = Config::new();
+        let pool = match db::Pool::new(&config.database) {
             Ok(v) => v,
             Err(e) => panic!("{}", e),
         };
-        let account_service = match account::AccountService::new() {
-            Ok(v) => v,
+        match pool.migrate() {
             Err(e) => panic!("{}", e),
+            Ok(version) => info!("database schema version {}", version),
         };
+        let ipc_service = ipc::IpcService::new(pool.clone());
+        let output_service = output::OutputService::new(pool.clone());
+        let account_service = account::AccountService::new(pool);
         match account_service.init_data() {
             Err(e) => panic!("{}", e),
             Ok(ret) => {
@@ -31,6 +54,26 @@ impl Service {
         Service {
             ipc_service,
             account_service,
+            output_service,
+            record_service: record::RecordService::new(config.record),
+            live_service: live::LiveService::new(config.live),
+            publisher_config: config.publisher,
         }
     }
+
+    /// 在 actix 的阻塞线程池中执行数据库操作，避免 SQLite 阻塞 HTTP 工作线程
+    pub async fn block<F, T>(self: &Arc<Self>, f: F) -> rusqlite::Result<T>
+    where
+        F: FnOnce(&Service) -> rusqlite::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let service = Arc::clone(self);
+        web::block(move || f(&service)).await.map_err(|e| match e {
+            BlockingError::Error(e) => e,
+            BlockingError::Canceled => rusqlite::Error::SqliteFailure(
+                ffi::Error::new(ffi::SQLITE_ABORT),
+                Some("Thread pool is gone".to_string()),
+            ),
+        })
+    }
 }
@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Ipc 的附加推流地址，与 tb_ipc 中的推流地址共用同一路输入
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Output {
+    pub id: i32,
+    pub ipc_id: i32,
+    pub url: String,
+    pub output_format: String, // flv rtsp srt udp
+    pub output_options: BTreeMap<String, String>,
+    pub enable: i32, // 0 不推流  1 随Ipc一起推流
+    pub status: i32, // 0 未推流  1 推流中  2 异常等待重试  3 推流失败
+    pub reason: Option<String>,
+    pub create_time: i64,
+    pub update_time: Option<i64>,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output {
+            id: 0,
+            ipc_id: 0,
+            url: String::new(),
+            output_format: "flv".to_string(),
+            output_options: BTreeMap::new(),
+            enable: 1,
+            status: 0,
+            reason: None,
+            create_time: 0,
+            update_time: None,
+        }
+    }
+}
+
+use super::ipc::{options_from_row, options_to_sql};
+use crate::db;
+use rusqlite::{params, Result, Row};
+
+const INSERT_SQL: &str = "INSERT INTO tb_ipc_output(ipc_id, url, output_format, output_options, enable, create_time) VALUES(?,?,?,?,?,?)";
+const UPDATE_SQL: &str = "UPDATE tb_ipc_output SET url=?, output_format=?, output_options=?, enable=?, update_time=? WHERE id=?";
+const UPDATE_STATUS_SQL: &str = "UPDATE tb_ipc_output SET status=?, reason=? WHERE id=?";
+const DELETE_SQL: &str = "DELETE FROM tb_ipc_output WHERE id=?";
+const GET_BY_ID_SQL: &str = "SELECT * FROM tb_ipc_output WHERE id=?";
+const GET_LIST_SQL: &str = "SELECT * FROM tb_ipc_output WHERE ipc_id=?";
+
+/// 将查询结果中的一行转换为Output
+fn from_row(row: &Row) -> Result<Output> {
+    Ok(Output {
+        id: row.get("id")?,
+        ipc_id: row.get("ipc_id")?,
+        url: row.get("url")?,
+        output_format: row.get("output_format")?,
+        output_options: options_from_row(row, "output_options")?,
+        enable: row.get("enable")?,
+        status: row.get("status")?,
+        reason: row.get("reason")?,
+        create_time: row.get("create_time")?,
+        update_time: row.get("update_time")?,
+    })
+}
+
+#[derive(Clone)]
+pub struct OutputService {
+    pool: db::Pool,
+}
+
+impl OutputService {
+    pub fn new(pool: db::Pool) -> Self {
+        OutputService { pool }
+    }
+
+    /// 执行Insert SQL往数据库中添加一条Output数据
+    pub fn insert(&self, output: Output) -> Result<usize> {
+        self.pool.conn()?.execute(
+            INSERT_SQL,
+            params![
+                output.ipc_id,
+                output.url,
+                output.output_format,
+                options_to_sql(&output.output_options),
+                output.enable,
+                output.create_time
+            ],
+        )
+    }
+
+    /// 执行Update SQL修改推流地址及参数，状态由推流任务修改
+    pub fn update(&self, output: Output) -> Result<usize> {
+        self.pool.conn()?.execute(
+            UPDATE_SQL,
+            params![
+                output.url,
+                output.output_format,
+                options_to_sql(&output.output_options),
+                output.enable,
+                output.update_time,
+                output.id
+            ],
+        )
+    }
+
+    /// 修改推流状态及异常原因
+    pub fn update_status(&self, id: i32, status: i32, reason: Option<String>) -> Result<usize> {
+        self.pool
+            .conn()?
+            .execute(UPDATE_STATUS_SQL, params![status, reason, id])
+    }
+
+    /// 执行Delete SQL从数据库中删除一条Output数据
+    pub fn delete(&self, id: i32) -> Result<usize> {
+        self.pool.conn()?.execute(DELETE_SQL, params![id])
+    }
+
+    /// 通过id来获取一条Output数据
+    pub fn get(&self, id: i32) -> Result<Option<Output>> {
+        let conn = self.pool.conn()?;
+        let mut stmt = conn.prepare(GET_BY_ID_SQL)?;
+        let mut rows = stmt.query_map(params![id], from_row)?;
+        let row = match rows.next() {
+            None => None,
+            Some(row) => Some(row?),
+        };
+        Ok(row)
+    }
+
+    /// 获取Ipc的推流地址列表
+    pub fn get_list(&self, ipc_id: i32) -> Result<Vec<Output>> {
+        let conn = self.pool.conn()?;
+        let mut stmp = conn.prepare(GET_LIST_SQL)?;
+        let rows = stmp.query_map(params![ipc_id], from_row)?;
+        let mut row_list: Vec<Output> = Vec::new();
+        for row in rows {
+            row_list.push(row?);
+        }
+        Ok(row_list)
+    }
+
+    /// 获取Ipc启用的推流地址列表
+    pub fn get_enable_list(&self, ipc_id: i32) -> Result<Vec<Output>> {
+        let conn = self.pool.conn()?;
+        let mut sql = String::from(GET_LIST_SQL);
+        sql += " AND enable = 1";
+        let mut stmp = conn.prepare(&sql)?;
+        let rows = stmp.query_map(params![ipc_id], from_row)?;
+        let mut row_list: Vec<Output> = Vec::new();
+        for row in rows {
+            row_list.push(row?);
+        }
+        Ok(row_list)
+    }
+}
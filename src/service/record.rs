@@ -0,0 +1,172 @@
+use crate::config;
+use crate::util;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use log::warn;
+
+/// 一个录像分段，文件名为分段开始时间的时间戳
+#[derive(Debug, Serialize, Clone)]
+pub struct Segment {
+    pub name: String,
+    pub start_time: i64, // 单位毫秒
+    pub end_time: i64,   // 文件最后修改时间，单位毫秒
+    pub size: u64,
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// 读取目录中的录像分段，按开始时间排序
+fn read_segments(dir: &Path) -> io::Result<Vec<Segment>> {
+    let mut segments: Vec<Segment> = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let start_time = match path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<i64>().ok())
+        {
+            None => continue,
+            Some(start_time) => start_time * 1000,
+        };
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let end_time = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(start_time);
+        segments.push(Segment {
+            name: entry.file_name().to_string_lossy().to_string(),
+            start_time,
+            end_time: end_time.max(start_time),
+            size: metadata.len(),
+            path,
+        });
+    }
+    segments.sort_by_key(|segment| segment.start_time);
+    Ok(segments)
+}
+
+/// 删除一个录像分段，失败时记录日志，不影响其他分段的清理
+fn remove_segment(segment: &Segment) -> bool {
+    match fs::remove_file(&segment.path) {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("Remove recording {:?} failed: {}", segment.path, e);
+            false
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RecordService {
+    path: PathBuf,
+    max_age: u64,
+    max_size: u64,
+}
+
+impl RecordService {
+    pub fn new(config: config::Record) -> Self {
+        RecordService {
+            path: PathBuf::from(config.path),
+            max_age: config.max_age,
+            max_size: config.max_size,
+        }
+    }
+
+    /// Ipc的录像目录
+    pub fn dir(&self, ipc_id: i32) -> PathBuf {
+        self.path.join(ipc_id.to_string())
+    }
+
+    /// 删除Ipc的录像目录，目录不存在时忽略
+    pub fn remove(&self, ipc_id: i32) -> io::Result<()> {
+        match fs::remove_dir_all(self.dir(ipc_id)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// 获取与时间段有交集的录像分段，时间单位毫秒
+    #[allow(clippy::unnecessary_map_or)]
+    pub fn list(
+        &self,
+        ipc_id: i32,
+        start: Option<i64>,
+        end: Option<i64>,
+    ) -> io::Result<Vec<Segment>> {
+        let dir = self.dir(ipc_id);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut segments = read_segments(&dir)?;
+        segments.retain(|segment| {
+            start.map_or(true, |start| segment.end_time > start)
+                && end.map_or(true, |end| segment.start_time < end)
+        });
+        Ok(segments)
+    }
+
+    /// 删除过期的录像，超过磁盘空间上限时从最早的录像开始删除
+    /// `recording` 为正在录像的Ipc，这些Ipc最新的分段可能正在写入，不会被删除
+    pub fn prune(&self, recording: &HashSet<i32>) -> io::Result<usize> {
+        if !self.path.is_dir() {
+            return Ok(0);
+        }
+        let mut segments: Vec<Segment> = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+            let mut dir_segments = read_segments(&entry.path())?;
+            let id = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<i32>().ok());
+            let is_recording = matches!(id, Some(id) if recording.contains(&id));
+            if is_recording {
+                dir_segments.pop();
+            }
+            segments.append(&mut dir_segments);
+        }
+        segments.sort_by_key(|segment| segment.start_time);
+
+        let mut removed = 0;
+        let expire_time =
+            util::time::current_timestamp() as i64 - (self.max_age * 3600 * 1000) as i64;
+        let mut kept: Vec<Segment> = Vec::new();
+        for segment in segments {
+            if self.max_age > 0 && segment.end_time < expire_time {
+                if remove_segment(&segment) {
+                    removed += 1;
+                }
+            } else {
+                kept.push(segment);
+            }
+        }
+        if self.max_size > 0 {
+            let max_size = self.max_size * 1024 * 1024;
+            let mut total_size: u64 = kept.iter().map(|segment| segment.size).sum();
+            for segment in kept.iter() {
+                if total_size <= max_size {
+                    break;
+                }
+                // 删除失败的分段仍然占用空间，继续删除下一个
+                if remove_segment(segment) {
+                    total_size -= segment.size;
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+}
@@ -1,94 +1,83 @@
 use super::Service;
-use crate::config::Config;
-use crate::my_actor;
+use crate::my_actor::{CommandError, MyActor, Start};
+use crate::scheduler::JobResult;
 
+use actix::clock::{self, Duration};
 use actix::prelude::*;
-use async_std::task;
+use futures::future::{self, Either};
+use std::collections::HashSet;
 use std::sync::Arc;
-use std::thread;
-use std::time;
 
-use log::{error, info};
+use log::warn;
 
-/// 检查上次程序结束，是否存在未完成任务
-/// 如果存在就启动该任务
-pub async fn start_undone(service: Arc<Service>, addr: Arc<Addr<my_actor::MyActor>>) {
-    info!("{}", "Start unfinished tasks");
-    match service.ipc_service.get_enable_list() {
-        Err(e) => panic!("{}", e),
-        Ok(ipc_list) => {
-            for ipc in ipc_list.iter() {
-                // 启动推流任务
-                task::spawn(addr.send(my_actor::Ping(ipc.id, ipc.enable)));
-            }
-        }
-    }
-}
-
-/// 检测是否存在异常任务
-/// 如果存在就重启该任务
-pub async fn retry_abnormal(
-    config: Config,
-    service: Arc<Service>,
-    addr: Arc<Addr<my_actor::MyActor>>,
-) {
-    // 方法延后启动时间
-    let delay_time = time::Duration::from_millis(60000);
-    thread::sleep(delay_time);
-
-    let less_retry_count = config.publisher.max_retry_count;
-    let interval_time = time::Duration::from_millis(config.publisher.interval_time);
-    let task_interval_time = time::Duration::from_millis(config.publisher.task_interval_time);
+/// 检查上次程序结束，是否存在未完成或者退出时停止的任务
+/// 如果存在就分批启动这些任务，每批全部开始推流、启动失败或者等待超时后再启动下一批
+pub async fn start_undone(service: Arc<Service>, addr: Addr<MyActor>) -> JobResult {
+    let concurrency = service.publisher_config.resume_concurrency.max(1);
+    let timeout = Duration::from_secs(service.publisher_config.resume_timeout);
+    let mut started = 0;
+    let mut reconnecting = 0;
+    let mut failed = 0;
+    let mut waiting = 0;
+    let mut after_id = 0;
     loop {
-        info!("{}", "Retry abnormal task");
-        match service.ipc_service.get_list_by_reason(less_retry_count) {
-            Err(e) => panic!("{}", e),
-            Ok(ipc_list) => {
-                for ipc in ipc_list.iter() {
-                    info!("Retry abnormal task: {}", ipc.name);
-                    let mut ipc_clone = ipc.clone();
-                    // 数据库增加一次重试次数
-                    ipc_clone.retry_count += 1;
-                    ipc_clone.enable = 1;
-                    ipc_clone.reason = None;
-                    match service.ipc_service.update(ipc_clone) {
-                        Ok(_) => {
-                            // 启动推流任务
-                            task::spawn(addr.send(my_actor::Ping(ipc.id, 1)));
-                        }
-                        Err(e) => error!("{}", &e.to_string()),
-                    }
-                    thread::sleep(task_interval_time); // 每个任务间隔时间
+        let ipc_list = service
+            .ipc_service
+            .get_resume_page(after_id, concurrency as u32)
+            .map_err(|e| e.to_string())?;
+        let ipc = match ipc_list.last() {
+            None => break,
+            Some(ipc) => ipc,
+        };
+        after_id = ipc.id;
+        // 启动推流任务，超时后推流继续在后台启动
+        let results = future::join_all(ipc_list.iter().map(|ipc| {
+            let start = addr.send(Start(ipc.id));
+            future::select(start, Box::pin(clock::delay_for(timeout)))
+        }))
+        .await;
+        for (ipc, result) in ipc_list.iter().zip(results) {
+            match result {
+                Either::Left((Ok(Ok(())), _)) => started += 1,
+                Either::Left((Ok(Err(CommandError::Reconnecting(e))), _)) => {
+                    reconnecting += 1;
+                    warn!("Resume ipc {} reconnecting: {}", ipc.id, e);
+                }
+                Either::Left((Ok(Err(e)), _)) => {
+                    failed += 1;
+                    warn!("Resume ipc {} failed: {}", ipc.id, e);
+                }
+                Either::Left((Err(e), _)) => {
+                    failed += 1;
+                    warn!("Resume ipc {} failed: {}", ipc.id, e);
+                }
+                Either::Right(_) => {
+                    waiting += 1;
+                    warn!("Resume ipc {} still starting after {:?}", ipc.id, timeout);
                 }
             }
         }
-        thread::sleep(interval_time); // 定时查询时间
     }
+    Ok(format!(
+        "{} unfinished tasks started, {} reconnecting, {} failed, {} still starting",
+        started, reconnecting, failed, waiting
+    ))
 }
 
-/// 状态检查
-/// 1、将`enable=1`的`retry_count`重置为0
-pub async fn status_check(service: Arc<Service>) {
-    // 方法延后启动时间
-    let delay_time = time::Duration::from_millis(30000);
-
-    loop {
-        thread::sleep(delay_time);
-        info!("{}", "Status check task");
-        match service.ipc_service.get_enable_list() {
-            Err(e) => panic!("{}", e),
-            Ok(ipc_list) => {
-                for ipc in ipc_list.iter() {
-                    let mut ipc_clone = ipc.clone();
-                    ipc_clone.retry_count = 0;
-                    ipc_clone.reason = None;
-
-                    match service.ipc_service.update(ipc_clone) {
-                        Ok(_) => {}
-                        Err(e) => error!("{}", &e.to_string()),
-                    }
-                }
-            }
-        }
-    }
+/// 清理过期录像
+pub fn record_prune(service: &Service) -> JobResult {
+    // 正在推流并且开启了录像的Ipc
+    let recording: HashSet<i32> = service
+        .ipc_service
+        .get_active_list()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .filter(|ipc| ipc.record == 1)
+        .map(|ipc| ipc.id)
+        .collect();
+    let removed = service
+        .record_service
+        .prune(&recording)
+        .map_err(|e| e.to_string())?;
+    Ok(format!("{} recordings removed", removed))
 }